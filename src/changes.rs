@@ -0,0 +1,231 @@
+use std::{fmt};
+use std::path::{Path};
+
+use serde::{Serialize};
+
+use crate::dns;
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Created,
+    Updated,
+    Unchanged
+}
+
+impl fmt::Display for Status {
+
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Status::Created => write!(f, "created"),
+            Status::Updated => write!(f, "updated"),
+            Status::Unchanged => write!(f, "unchanged")
+        }
+    }
+}
+
+/// lines starting every generated zone file
+const HEADER: &str = concat!(
+    "; ------------------------------------------------------------------------------\n",
+    "; zone file generated from dns-zones-builder\n"
+);
+
+/// the header comments of a zone file. the path of the file is followed by
+/// details of the build like the profile and view used
+pub fn header(path: &Path, details: &[String]) -> String {
+    let mut rtn = String::from(HEADER);
+    rtn.push_str(&format!("; {}\n", path.display()));
+
+    for detail in details {
+        rtn.push_str(&format!("; {}\n", detail));
+    }
+
+    rtn
+}
+
+/// the details of a build written to the header of its zone files
+pub fn details(profile: Option<&str>, view: Option<&str>) -> Vec<String> {
+    profile.map(|profile| format!("profile {}", profile)).into_iter()
+        .chain(view.map(|view| format!("view {}", view)))
+        .collect()
+}
+
+/// splits an existing zone file into the details of its header and its
+/// records. the fixed lines and the path of the header are skipped
+fn split_existing(contents: &str) -> (Vec<&str>, &str) {
+    let mut comments = Vec::new();
+    let mut rest = contents;
+
+    while rest.starts_with(';') {
+        let (line, next) = rest.split_once('\n').unwrap_or((rest, ""));
+
+        comments.push(line);
+        rest = next;
+    }
+
+    let details = comments.into_iter()
+        .skip(HEADER.lines().count() + 1)
+        .map(|line| line.trim_start_matches(';').trim_start())
+        .collect();
+
+    (details, rest)
+}
+
+/// splits a TXT record line into the fields before the data and the data
+fn txt_record(line: &str) -> Option<(&str, dns::record::TxtData)> {
+    let (fields, data) = line.split_once("\tIN\tTXT\t")?;
+
+    Some((fields, dns::record::TxtData::parse(data)?))
+}
+
+/// compares a line of a zone with a line of its existing file. the data of
+/// TXT records is compared joined so that split and unsplit forms are equal
+fn same_line(given: &str, existing: &str) -> bool {
+    if given == existing {
+        return true;
+    }
+
+    match (txt_record(given), txt_record(existing)) {
+        (Some(given), Some(existing)) => given == existing,
+        _ => false
+    }
+}
+
+/// reads the serial of the soa record at the start of a zone body
+fn existing_serial(body: &str) -> Option<u32> {
+    let (_, values) = body.lines().next()?.split_once('(')?;
+
+    values.split_whitespace().next()?.parse().ok()
+}
+
+/// compares a zone and the details of its header with the contents of its
+/// existing file, ignoring the path in the header and the soa serial. when
+/// the file differs and the serial is not newer than the existing one the
+/// serial is bumped past it
+pub fn compare(zone: &mut dns::zone::Zone, details: &[String], existing: Option<&[u8]>) -> Status {
+    let existing = match existing {
+        Some(existing) => String::from_utf8_lossy(existing),
+        None => return Status::Created
+    };
+    let (existing_details, body) = split_existing(&existing);
+    let (serial, previous) = match (zone.get_serial(), existing_serial(body)) {
+        (Some(serial), Some(previous)) => (serial, previous),
+        _ => return Status::Updated
+    };
+
+    zone.set_serial(previous);
+
+    let rendered = format!("{}\n", zone);
+
+    if existing_details == details &&
+        rendered.lines().count() == body.lines().count() &&
+        rendered.lines().zip(body.lines()).all(|(given, existing)| same_line(given, existing))
+    {
+        return Status::Unchanged;
+    }
+
+    zone.set_serial(if serial > previous { serial } else { previous.wrapping_add(1) });
+
+    Status::Updated
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_txt_data_is_the_same() {
+        assert!(same_line("a.\t300\tIN\tTXT\t\"a\" \"b\"", "a.\t300\tIN\tTXT\t\"ab\""));
+        assert!(!same_line("a.\t300\tIN\tTXT\t\"a\" \"b\"", "a.\t300\tIN\tTXT\t\"a b\""));
+        assert!(!same_line("a.\t300\tIN\tTXT\t\"ab\"", "b.\t300\tIN\tTXT\t\"ab\""));
+        assert!(!same_line("a.\t300\tIN\tA\t10.0.0.1", "a.\t300\tIN\tA\t10.0.0.2"));
+    }
+
+    #[test]
+    fn header_details() {
+        let header = header(Path::new("/zones/a.example"), &["profile staging".to_owned()]);
+        let contents = format!("{}a.\t300\tIN\tA\t10.0.0.1\n", header);
+
+        assert_eq!(split_existing(&contents), (vec!("profile staging"), "a.\t300\tIN\tA\t10.0.0.1\n"));
+
+        let header = super::header(Path::new("/zones/example"), &details(Some("staging"), Some("internal")));
+        assert!(header.ends_with("; /zones/example\n; profile staging\n; view internal\n"));
+        assert!(super::header(Path::new("/zones/example"), &details(None, None)).ends_with("; /zones/example\n"));
+    }
+
+    fn zone(serial: u32, address: [u8; 4]) -> dns::zone::Zone {
+        let mut zone = dns::zone::Zone::new("example".to_owned(), "example.".to_owned());
+        zone.add_record(dns::record::Record::Soa {
+            name: "example.".to_owned(),
+            ttl: 300,
+            domain: "ns1.example.".to_owned(),
+            email: "hostmaster.example.".to_owned(),
+            serial,
+            refresh: 3600,
+            retry: 600,
+            expire: 86400,
+            nct: 300
+        });
+        zone.add_record(dns::record::Record::A {
+            name: "www.example.".to_owned(),
+            ttl: 300,
+            address: address.into()
+        });
+
+        zone
+    }
+
+    fn file(zone: &dns::zone::Zone, details: &[String]) -> Vec<u8> {
+        format!("{}{}\n", header(Path::new("/zones/example"), details), zone).into_bytes()
+    }
+
+    #[test]
+    fn created() {
+        let mut given = zone(5, [10, 0, 0, 1]);
+
+        assert_eq!(compare(&mut given, &[], None), Status::Created);
+        assert_eq!(given.get_serial(), Some(5));
+    }
+
+    #[test]
+    fn unchanged_keeps_the_existing_serial() {
+        let existing = file(&zone(7, [10, 0, 0, 1]), &[]);
+        let mut given = zone(2026101800, [10, 0, 0, 1]);
+
+        assert_eq!(compare(&mut given, &[], Some(&existing)), Status::Unchanged);
+        assert_eq!(given.get_serial(), Some(7));
+    }
+
+    #[test]
+    fn updated_bumps_the_serial() {
+        let existing = file(&zone(7, [10, 0, 0, 1]), &[]);
+
+        let mut given = zone(7, [10, 0, 0, 2]);
+        assert_eq!(compare(&mut given, &[], Some(&existing)), Status::Updated);
+        assert_eq!(given.get_serial(), Some(8));
+
+        let mut given = zone(3, [10, 0, 0, 2]);
+        assert_eq!(compare(&mut given, &[], Some(&existing)), Status::Updated);
+        assert_eq!(given.get_serial(), Some(8));
+
+        let mut given = zone(20, [10, 0, 0, 2]);
+        assert_eq!(compare(&mut given, &[], Some(&existing)), Status::Updated);
+        assert_eq!(given.get_serial(), Some(20));
+    }
+
+    #[test]
+    fn updated_header_details() {
+        let existing = file(&zone(7, [10, 0, 0, 1]), &["profile staging".to_owned()]);
+
+        let mut given = zone(7, [10, 0, 0, 1]);
+        assert_eq!(compare(&mut given, &["profile production".to_owned()], Some(&existing)), Status::Updated);
+        assert_eq!(given.get_serial(), Some(8));
+
+        let mut given = zone(7, [10, 0, 0, 1]);
+        let details = ["profile staging".to_owned(), "view internal".to_owned()];
+        assert_eq!(compare(&mut given, &details, Some(&existing)), Status::Updated);
+
+        let mut given = zone(7, [10, 0, 0, 1]);
+        assert_eq!(compare(&mut given, &["profile staging".to_owned()], Some(&existing)), Status::Unchanged);
+    }
+}
@@ -1,63 +1,97 @@
 use std::collections::{HashMap};
 use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
 
-use serde::{Deserialize};
+use schemars::{JsonSchema};
+use serde::{Deserialize, Deserializer};
 
-#[derive(Deserialize, Debug)]
-#[serde(untagged)]
+use crate::net;
+
+#[derive(Deserialize, JsonSchema, Debug, Clone, PartialEq)]
+#[serde(untagged, expecting = "expected an ipv4 address or a string with key references")]
 pub enum Ipv4Value {
     Ip(Ipv4Addr),
     Str(String)
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, JsonSchema, Debug, Clone, PartialEq)]
 pub struct Ipv4Detail {
     pub ip: Ipv4Value,
     pub reverse: Option<bool>
 }
 
-#[derive(Deserialize, Debug)]
-#[serde(untagged)]
+#[derive(Deserialize, JsonSchema, Debug, Clone, PartialEq)]
+#[serde(
+    untagged,
+    expecting = "expected an ipv4 address, a string with key references, a map with \"ip\" or a map with \"pool\""
+)]
 pub enum Ipv4Type {
     Ip(Ipv4Addr),
     Str(String),
-    Detail(Ipv4Detail)
+    Detail(Ipv4Detail),
+    Pool(PoolRef)
 }
 
 // ----------------------------------------------------------------------------
 
-#[derive(Deserialize, Debug)]
-#[serde(untagged)]
+#[derive(Deserialize, JsonSchema, Debug, Clone, PartialEq)]
+#[serde(untagged, expecting = "expected an ipv6 address or a string with key references")]
 pub enum Ipv6Value {
     Ip(Ipv6Addr),
     Str(String)
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, JsonSchema, Debug, Clone, PartialEq)]
 pub struct Ipv6Detail {
     pub ip: Ipv6Value,
     pub reverse: Option<bool>
 }
 
-#[derive(Deserialize, Debug)]
-#[serde(untagged)]
+#[derive(Deserialize, JsonSchema, Debug, Clone, PartialEq)]
+#[serde(
+    untagged,
+    expecting = "expected an ipv6 address, a string with key references, a map with \"ip\" or a map with \"pool\""
+)]
 pub enum Ipv6Type {
     Ip(Ipv6Addr),
     Str(String),
-    Detail(Ipv6Detail)
+    Detail(Ipv6Detail),
+    Pool(PoolRef)
+}
+
+// ----------------------------------------------------------------------------
+
+#[derive(Deserialize, JsonSchema, Debug, Clone, PartialEq)]
+pub struct PoolRef {
+    pub pool: String,
+    pub reverse: Option<bool>
+}
+
+#[derive(Deserialize, JsonSchema, Debug, Clone)]
+pub struct Pool {
+    #[schemars(with = "String")]
+    pub prefix: net::IpAddrCidr,
+    pub reserved: Option<Vec<String>>
 }
 
+pub type PoolsMap = HashMap<String, Pool>;
+
 // ----------------------------------------------------------------------------
 
-#[derive(Deserialize, Debug)]
-#[serde(untagged)]
+#[derive(Deserialize, JsonSchema, Debug, Clone, PartialEq)]
+#[serde(
+    untagged,
+    expecting = "expected an ipv4 address, a string, a map with \"ip\" or \"pool\", or a list of them"
+)]
 pub enum Ipv4Address {
     Single(Ipv4Type),
     Multiple(Vec<Ipv4Type>)
 }
 
-#[derive(Deserialize, Debug)]
-#[serde(untagged)]
+#[derive(Deserialize, JsonSchema, Debug, Clone, PartialEq)]
+#[serde(
+    untagged,
+    expecting = "expected an ipv6 address, a string, a map with \"ip\" or \"pool\", or a list of them"
+)]
 pub enum Ipv6Address {
     Single(Ipv6Type),
     Multiple(Vec<Ipv6Type>)
@@ -65,15 +99,18 @@ pub enum Ipv6Address {
 
 // ----------------------------------------------------------------------------
 
-#[derive(Deserialize, Debug)]
-#[serde(untagged)]
+#[derive(Deserialize, JsonSchema, Debug, Clone, PartialEq)]
+#[serde(untagged, expecting = "expected an ip address or a string with key references")]
 pub enum PtrValue {
     Ip(IpAddr),
     Str(String)
 }
 
-#[derive(Deserialize, Debug)]
-#[serde(untagged)]
+#[derive(Deserialize, JsonSchema, Debug, Clone, PartialEq)]
+#[serde(
+    untagged,
+    expecting = "expected an ip address, a string or a list of them"
+)]
 pub enum PtrAddress {
     Single(PtrValue),
     Multiple(Vec<PtrValue>)
@@ -81,10 +118,199 @@ pub enum PtrAddress {
 
 // ----------------------------------------------------------------------------
 
-#[derive(Deserialize, Debug)]
-#[serde(untagged)]
+#[derive(Deserialize, JsonSchema, Debug, Clone)]
+#[serde(untagged, expecting = "expected a string or a non-empty list of strings")]
+pub enum TxtValue {
+    Single(String),
+    #[serde(deserialize_with = "non_empty")]
+    Multiple(#[schemars(length(min = 1))] Vec<String>)
+}
+
+impl TxtValue {
+
+    pub fn joined(&self) -> String {
+        match self {
+            TxtValue::Single(single) => single.clone(),
+            TxtValue::Multiple(multiple) => multiple.concat()
+        }
+    }
+}
+
+/// values are equal if their joined data is equal, regardless of how they
+/// were split
+impl PartialEq for TxtValue {
+
+    fn eq(&self, other: &Self) -> bool {
+        self.joined() == other.joined()
+    }
+}
+
+/// rejects empty lists since they would produce a record without data
+fn non_empty<'de, D, T>(deserializer: D) -> Result<Vec<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>
+{
+    let rtn = Vec::<T>::deserialize(deserializer)?;
+
+    if rtn.is_empty() {
+        Err(serde::de::Error::invalid_length(0, &"at least one value"))
+    } else {
+        Ok(rtn)
+    }
+}
+
+// ----------------------------------------------------------------------------
+
+#[derive(Deserialize, JsonSchema, Debug, Clone, PartialEq)]
+pub enum SpfAll {
+    Pass,
+    Neutral,
+    SoftFail,
+    Fail
+}
+
+#[derive(Deserialize, JsonSchema, Debug, Clone, PartialEq)]
+pub struct SpfDetail {
+    pub include: Option<Vec<String>>,
+    pub ip4: Option<Vec<String>>,
+    pub ip6: Option<Vec<String>>,
+    pub a: Option<bool>,
+    pub mx: Option<bool>,
+    pub redirect: Option<String>,
+    pub all: Option<SpfAll>
+}
+
+#[derive(Deserialize, JsonSchema, Debug, Clone, PartialEq)]
+pub enum DkimKeyType {
+    Rsa,
+    Ed25519
+}
+
+#[derive(Deserialize, JsonSchema, Debug, Clone, PartialEq)]
+pub struct DkimDetail {
+    pub selector: String,
+    pub key: Option<String>,
+    pub key_file: Option<String>,
+    pub key_type: Option<DkimKeyType>,
+    pub testing: Option<bool>
+}
+
+#[derive(Deserialize, JsonSchema, Debug, Clone, PartialEq)]
+pub enum DmarcPolicy {
+    None,
+    Quarantine,
+    Reject
+}
+
+#[derive(Deserialize, JsonSchema, Debug, Clone, PartialEq)]
+pub enum DmarcAlignment {
+    Relaxed,
+    Strict
+}
+
+#[derive(Deserialize, JsonSchema, Debug, Clone, PartialEq)]
+pub struct DmarcDetail {
+    pub policy: DmarcPolicy,
+    pub subdomain_policy: Option<DmarcPolicy>,
+    pub pct: Option<u8>,
+    pub rua: Option<Vec<String>>,
+    pub ruf: Option<Vec<String>>,
+    pub adkim: Option<DmarcAlignment>,
+    pub aspf: Option<DmarcAlignment>
+}
+
+// ----------------------------------------------------------------------------
+
+#[derive(Deserialize, JsonSchema, Debug, Clone, PartialEq)]
+pub enum TlsaProtocol {
+    Tcp,
+    Udp,
+    Sctp
+}
+
+#[derive(Deserialize, JsonSchema, Debug, Clone, PartialEq)]
+pub enum TlsaUsage {
+    PkixTa,
+    PkixEe,
+    DaneTa,
+    DaneEe
+}
+
+#[derive(Deserialize, JsonSchema, Debug, Clone, PartialEq)]
+pub enum TlsaSelector {
+    Cert,
+    Spki
+}
+
+#[derive(Deserialize, JsonSchema, Debug, Clone, PartialEq)]
+pub enum TlsaMatching {
+    Full,
+    Sha256,
+    Sha512
+}
+
+#[derive(Deserialize, JsonSchema, Debug, Clone, PartialEq)]
+pub struct TlsaDetail {
+    pub port: u16,
+    pub protocol: Option<TlsaProtocol>,
+    pub usage: Option<TlsaUsage>,
+    pub selector: Option<TlsaSelector>,
+    pub matching: Option<TlsaMatching>,
+    pub data: Option<String>,
+    pub cert_file: Option<String>
+}
+
+#[derive(Deserialize, JsonSchema, Debug, Clone, PartialEq)]
+pub enum SshfpAlgorithm {
+    Rsa,
+    Dsa,
+    Ecdsa,
+    Ed25519,
+    Ed448
+}
+
+#[derive(Deserialize, JsonSchema, Debug, Clone, PartialEq)]
+pub enum SshfpFingerprint {
+    Sha1,
+    Sha256
+}
+
+#[derive(Deserialize, JsonSchema, Debug, Clone, PartialEq)]
+pub struct SshfpDetail {
+    pub algorithm: Option<SshfpAlgorithm>,
+    pub fingerprint_type: Option<SshfpFingerprint>,
+    pub fingerprint: Option<String>,
+    pub key_file: Option<String>
+}
+
+// ----------------------------------------------------------------------------
+
+#[derive(Deserialize, JsonSchema, Debug, Clone, PartialEq)]
+pub struct SvcParams {
+    pub mandatory: Option<Vec<String>>,
+    pub alpn: Option<Vec<String>>,
+    pub no_default_alpn: Option<bool>,
+    pub port: Option<u16>,
+    pub ipv4hint: Option<Vec<Ipv4Value>>,
+    pub ech: Option<String>,
+    pub ipv6hint: Option<Vec<Ipv6Value>>
+}
+
+#[derive(Deserialize, JsonSchema, Debug, Clone, PartialEq)]
+pub struct SvcbDetail {
+    pub priority: u16,
+    pub target: String,
+    pub params: Option<SvcParams>
+}
+
+// ----------------------------------------------------------------------------
+
+#[derive(Deserialize, JsonSchema, Debug, Clone, PartialEq)]
+#[serde(untagged, expecting = "expected a bool or a string")]
 pub enum ReverseValue {
     Bool(bool),
+    #[allow(dead_code)]
     Str(String)
 }
 
@@ -92,7 +318,7 @@ type DomainName = Option<String>;
 
 pub type KeysMap = HashMap<String, String>;
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, JsonSchema, Debug, Clone, PartialEq)]
 #[serde(tag = "type")]
 pub enum Record {
     Soa {
@@ -130,38 +356,130 @@ pub enum Record {
     },
 
     Txt {
-        value: String
+        value: TxtValue
     },
 
     Ptr {
         address: PtrAddress
+    },
+
+    Spf(SpfDetail),
+
+    Dkim(DkimDetail),
+
+    Dmarc(DmarcDetail),
+
+    Tlsa(TlsaDetail),
+
+    Sshfp(SshfpDetail),
+
+    Svcb(SvcbDetail),
+
+    Https(SvcbDetail)
+}
+
+impl Record {
+
+    /// the type of the record as written in zone files, e.g. "AAAA". SPF,
+    /// DKIM and DMARC are named after the builder even though they are
+    /// written as TXT records
+    pub fn get_type(&self) -> &'static str {
+        match self {
+            Record::Soa {..} => "SOA",
+            Record::Ns {..} => "NS",
+            Record::A {..} => "A",
+            Record::Aaaa {..} => "AAAA",
+            Record::Mx {..} => "MX",
+            Record::Cname {..} => "CNAME",
+            Record::Txt {..} => "TXT",
+            Record::Ptr {..} => "PTR",
+            Record::Spf(_) => "SPF",
+            Record::Dkim(_) => "DKIM",
+            Record::Dmarc(_) => "DMARC",
+            Record::Tlsa(_) => "TLSA",
+            Record::Sshfp(_) => "SSHFP",
+            Record::Svcb(_) => "SVCB",
+            Record::Https(_) => "HTTPS"
+        }
     }
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, JsonSchema, Debug, Clone)]
+pub struct SubDomainRange {
+    pub from: u32,
+    pub to: u32,
+    pub format: String
+}
+
+#[derive(Deserialize, JsonSchema, Debug, Clone)]
+pub struct SoaDetail {
+    pub primary: Option<String>,
+    pub email: Option<String>,
+
+    pub serial: Option<u32>,
+    pub refresh: Option<u32>,
+    pub retry: Option<u32>,
+    pub expire: Option<u32>,
+    pub nct: Option<u32>
+}
+
+#[derive(Deserialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SubDomainMerge {
+    Append,
+    Replace
+}
+
+#[derive(Deserialize, JsonSchema, Debug, Clone, PartialEq)]
+pub struct SubDomainRecord {
+    pub views: Option<Vec<String>>,
+
+    #[serde(flatten)]
+    pub record: Record
+}
+
+#[derive(Deserialize, JsonSchema, Debug, Clone)]
 pub struct SubDomain {
     pub name: String,
     pub domain: DomainName,
 
+    pub views: Option<Vec<String>>,
+
+    pub merge: Option<SubDomainMerge>,
+
     pub reverse: Option<ReverseValue>,
 
-    pub records: Vec<Record>,
+    pub range: Option<SubDomainRange>,
+
+    pub keys: Option<KeysMap>,
+
+    pub records: Vec<SubDomainRecord>,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, JsonSchema, Debug, Clone)]
 pub enum ReverseType {
     V4, V6
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, JsonSchema, Debug, Clone)]
+pub struct ReverseFill {
+    pub template: String,
+    pub range: Option<String>,
+    pub forward: Option<String>
+}
+
+#[derive(Deserialize, JsonSchema, Debug, Clone)]
 pub struct ReverseZone {
-    pub r#type: ReverseType,
-    pub addr: String,
-    pub cidr: u8,
-    pub as_domain: Option<bool>
+    pub r#type: Option<ReverseType>,
+    #[schemars(with = "Option<String>")]
+    pub prefix: Option<net::IpAddrCidr>,
+    pub addr: Option<String>,
+    pub cidr: Option<u8>,
+    #[allow(dead_code)]
+    pub as_domain: Option<bool>,
+    pub fill: Option<ReverseFill>
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, JsonSchema, Debug, Clone)]
 pub struct Zone {
     pub name: String,
     pub domain: DomainName,
@@ -173,16 +491,146 @@ pub struct Zone {
 
     pub keys: Option<KeysMap>,
 
+    pub pools: Option<PoolsMap>,
+
+    pub idn_comments: Option<bool>,
+
+    pub hooks: Option<Vec<Hook>>,
+
+    pub soa: Option<SoaDetail>,
+    pub nameservers: Option<Vec<String>>,
+
+    pub extends: Option<Vec<String>>,
+
+    #[serde(default)]
+    pub subdomains: Vec<SubDomain>
+}
+
+#[derive(Deserialize, JsonSchema, Debug, Clone)]
+pub struct ZoneTemplate {
+    pub ttl: Option<usize>,
+    pub reverse: Option<ReverseValue>,
+
+    pub keys: Option<KeysMap>,
+
+    pub soa: Option<SoaDetail>,
+    pub nameservers: Option<Vec<String>>,
+
+    pub extends: Option<Vec<String>>,
+
+    #[serde(default)]
+    pub subdomains: Vec<SubDomain>
+}
+
+pub type TemplatesMap = HashMap<String, ZoneTemplate>;
+
+#[derive(Deserialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CheckLevel {
+    Ignore,
+    Warn,
+    Fail
+}
+
+#[derive(Deserialize, JsonSchema, Debug, Clone)]
+pub struct ProfileRemove {
+    pub subdomain: String,
+    pub r#type: Option<String>
+}
+
+#[derive(Deserialize, JsonSchema, Debug, Clone)]
+pub struct ProfileZone {
+    pub ttl: Option<usize>,
+    pub keys: Option<KeysMap>,
+    pub nameservers: Option<Vec<String>>,
+
+    pub remove: Option<Vec<ProfileRemove>>,
+
+    #[serde(default)]
     pub subdomains: Vec<SubDomain>
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, JsonSchema, Debug, Clone)]
+pub struct Profile {
+    pub ttl: Option<usize>,
+    pub keys: Option<KeysMap>,
+    pub nameservers: Option<Vec<String>>,
+
+    pub zones: Option<HashMap<String, ProfileZone>>
+}
+
+pub type ProfilesMap = HashMap<String, Profile>;
+
+#[derive(Deserialize, JsonSchema, Debug, Clone)]
+pub struct View {
+    pub name: String,
+    pub match_clients: Option<Vec<String>>
+}
+
+#[derive(Deserialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HookFailure {
+    Fail,
+    Rollback
+}
+
+#[derive(Deserialize, JsonSchema, Debug, Clone)]
+pub struct Hook {
+    /// split into arguments like a shell would, quotes and backslashes are
+    /// honored but nothing is expanded. "{zone}", "{origin}" and "{path}"
+    /// are replaced in every argument
+    pub command: String,
+    pub on_failure: Option<HookFailure>
+}
+
+#[derive(Deserialize, JsonSchema, Debug, Clone)]
 pub struct Config {
     pub name: String,
     pub directory: Option<String>,
 
     pub keys: Option<KeysMap>,
 
+    pub pools: Option<PoolsMap>,
+
+    pub idn_comments: Option<bool>,
+
+    pub address_conflicts: Option<CheckLevel>,
+
+    pub backups: Option<usize>,
+
+    pub hooks: Option<Vec<Hook>>,
+
+    pub soa: Option<SoaDetail>,
+    pub nameservers: Option<Vec<String>>,
+
+    pub templates: Option<TemplatesMap>,
+
+    pub views: Option<Vec<View>>,
+
+    pub profiles: Option<ProfilesMap>,
+
     pub zones: Vec<Zone>
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_txt_values_are_equal() {
+        let split = TxtValue::Multiple(vec!("v=spf1 ".to_owned(), "-all".to_owned()));
+
+        assert_eq!(split, TxtValue::Single("v=spf1 -all".to_owned()));
+        assert_ne!(split, TxtValue::Single("v=spf1 ~all".to_owned()));
+    }
+
+    #[test]
+    fn reverse_values() {
+        let subdomain = |reverse: &str| -> SubDomain {
+            serde_yaml::from_str(&format!("{{name: www, reverse: {}, records: []}}", reverse)).unwrap()
+        };
+
+        assert_eq!(subdomain("true").reverse, Some(ReverseValue::Bool(true)));
+        assert_eq!(subdomain("\"yes\"").reverse, Some(ReverseValue::Str("yes".to_owned())));
+        assert!(serde_yaml::from_str::<SubDomain>("{name: www, reverse: [true], records: []}").is_err());
+    }
+}
@@ -0,0 +1,224 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::net::{IpAddr};
+
+use log::{warn};
+
+use crate::config;
+use crate::dns;
+use crate::error;
+use crate::net;
+
+/// summarizes the addresses that wanted a reverse record but have no
+/// reverse zone as the prefixes covering them
+fn uncovered_prefixes(storage: &dns::ZoneStorage) -> error::Result<Vec<String>> {
+    let mut uncovered_v4: Vec<net::Ipv4AddrCidr> = Vec::new();
+    let mut uncovered_v6: Vec<net::Ipv6AddrCidr> = Vec::new();
+
+    for (ip, _) in storage.get_unmatched_reverse_ref() {
+        match ip {
+            IpAddr::V4(v4) => uncovered_v4.push(net::Ipv4AddrCidr::from_addr(*v4, 32)?),
+            IpAddr::V6(v6) => uncovered_v6.push(net::Ipv6AddrCidr::from_addr(*v6, 128)?)
+        }
+    }
+
+    Ok(net::Ipv4AddrCidr::aggregate(&uncovered_v4).iter()
+        .map(|prefix| prefix.to_string())
+        .chain(net::Ipv6AddrCidr::aggregate(&uncovered_v6).iter().map(|prefix| prefix.to_string()))
+        .collect())
+}
+
+/// checks every A, AAAA and PTR record of the storage for addresses that are
+/// assigned to more than one name, addresses that have more than one PTR
+/// target and addresses that wanted a reverse record but have no reverse
+/// zone. depending on the level the problems are ignored, printed or fail
+/// the build. printed problems are kept with the zones they were found in
+pub fn check_addresses(storage: &mut dns::ZoneStorage, level: config::CheckLevel) -> error::Result<()> {
+    if level == config::CheckLevel::Ignore {
+        return Ok(());
+    }
+
+    let unmatched = storage.get_unmatched_reverse_ref();
+    let mut problems: Vec<(Vec<String>, String)> = unmatched.iter()
+        .map(|(ip, domain)| (
+            storage.find_zone(domain).cloned().into_iter().collect(),
+            format!("failed to find reverse zone for ip address: {} ({})", ip, domain)
+        ))
+        .collect();
+    let mut forward: BTreeMap<IpAddr, (BTreeSet<String>, BTreeSet<String>)> = BTreeMap::new();
+    let mut reverse: BTreeMap<IpAddr, (BTreeSet<String>, BTreeSet<String>)> = BTreeMap::new();
+
+    for zone in storage.get_zones_ref().values() {
+        for record in zone.get_records_ref() {
+            let (found, ip, domain) = match record {
+                dns::record::Record::A {name, address, ..} => (&mut forward, IpAddr::V4(*address), name),
+                dns::record::Record::Aaaa {name, address, ..} => (&mut forward, IpAddr::V6(*address), name),
+                dns::record::Record::Ptr {name, domain, ..} => match dns::reverse_name_to_ip(name) {
+                    Some(ip) => (&mut reverse, ip, domain),
+                    None => continue
+                },
+                _ => continue
+            };
+            let (names, zones) = found.entry(ip).or_default();
+
+            names.insert(dns::normalize_domain(domain));
+            zones.insert(zone.get_name());
+        }
+    }
+
+    for (ip, (names, zones)) in &forward {
+        if names.len() > 1 {
+            problems.push((zones.iter().cloned().collect(), format!(
+                "address {} is assigned to multiple names: {}",
+                ip, names.iter().cloned().collect::<Vec<String>>().join(", ")
+            )));
+        }
+    }
+
+    for (ip, (targets, zones)) in &reverse {
+        if targets.len() > 1 {
+            problems.push((zones.iter().cloned().collect(), format!(
+                "address {} has multiple PTR targets: {}",
+                ip, targets.iter().cloned().collect::<Vec<String>>().join(", ")
+            )));
+        }
+    }
+
+    let count = problems.len();
+    let summary = if unmatched.len() > 1 {
+        Some(uncovered_prefixes(storage)?.join(", "))
+    } else {
+        None
+    };
+
+    for (zones, problem) in problems {
+        storage.add_warning(&zones, problem);
+    }
+
+    if let Some(summary) = summary {
+        warn!("addresses without a reverse zone: {}", summary);
+    }
+
+    if level == config::CheckLevel::Fail && count > 0 {
+        Err(error::RuntimeError::ConfigError(format!(
+            "found {} address problems", count
+        )))
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{Ipv4Addr, Ipv6Addr};
+
+    use super::*;
+
+    fn a(name: &str, address: [u8; 4]) -> dns::record::Record {
+        dns::record::Record::A { name: name.to_owned(), ttl: 300, address: Ipv4Addr::from(address) }
+    }
+
+    fn ptr(ip: IpAddr, domain: &str) -> dns::record::Record {
+        dns::record::Record::Ptr { name: dns::ip_reverse_string(&ip, true).unwrap(), ttl: 300, domain: domain.to_owned() }
+    }
+
+    fn storage() -> dns::ZoneStorage {
+        let mut storage = dns::ZoneStorage::new();
+        let prefix: net::Ipv4AddrCidr = "10.0.0.0/24".parse().unwrap();
+
+        storage.add_zone(dns::zone::Zone::new("example".to_owned(), "example.".to_owned()));
+        storage.add_zone(dns::zone::Zone::new("other".to_owned(), "other.test.".to_owned()));
+        storage.add_v4_rev_zone(
+            dns::zone::Zone::new("rev".to_owned(), dns::ipv4_reverse_prefix(&prefix, true).unwrap()), prefix
+        );
+
+        storage
+    }
+
+    fn warnings(storage: &dns::ZoneStorage, zone: &str) -> Vec<String> {
+        storage.get_zones_ref()[zone].get_warnings_ref().clone()
+    }
+
+    #[test]
+    fn duplicate_names() {
+        let mut storage = storage();
+        storage.add_record_to(&"example".to_owned(), a("www.example.", [10, 0, 0, 1]));
+        storage.add_record_to(&"example".to_owned(), a("WWW.example.", [10, 0, 0, 1]));
+        storage.add_record_to(&"other".to_owned(), a("mail.other.test.", [10, 0, 0, 1]));
+        storage.add_record_to(&"example".to_owned(), dns::record::Record::Aaaa {
+            name: "www.example.".to_owned(), ttl: 300, address: "2001:db8::1".parse::<Ipv6Addr>().unwrap()
+        });
+        storage.add_record_to(&"example".to_owned(), dns::record::Record::Aaaa {
+            name: "ftp.example.".to_owned(), ttl: 300, address: "2001:db8::1".parse::<Ipv6Addr>().unwrap()
+        });
+
+        check_addresses(&mut storage, config::CheckLevel::Warn).unwrap();
+
+        let expected = vec!(
+            "address 10.0.0.1 is assigned to multiple names: mail.other.test., www.example.".to_owned(),
+            "address 2001:db8::1 is assigned to multiple names: ftp.example., www.example.".to_owned()
+        );
+        assert_eq!(warnings(&storage, "example"), expected);
+        assert_eq!(warnings(&storage, "other"), expected[..1]);
+        assert!(warnings(&storage, "rev").is_empty());
+    }
+
+    #[test]
+    fn multiple_ptr_targets() {
+        let mut storage = storage();
+        let ip = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 5));
+        storage.add_record_to(&"rev".to_owned(), ptr(ip, "a.example."));
+        storage.add_record_to(&"rev".to_owned(), ptr(ip, "b.example."));
+        storage.add_record_to(&"rev".to_owned(), ptr(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 6)), "a.example."));
+
+        check_addresses(&mut storage, config::CheckLevel::Warn).unwrap();
+
+        assert_eq!(warnings(&storage, "rev"), vec!("address 10.0.0.5 has multiple PTR targets: a.example., b.example."));
+        assert!(warnings(&storage, "example").is_empty());
+    }
+
+    #[test]
+    fn unmatched_reverse() {
+        let mut storage = storage();
+        storage.set_current("other".to_owned());
+
+        for (last, name) in [(2, "a.other.test."), (3, "b.other.test.")] {
+            let ip = Ipv4Addr::new(192, 0, 2, last);
+            assert!(!storage.add_v4_reverse_record(&ip, ptr(IpAddr::V4(ip), name)));
+        }
+
+        assert_eq!(uncovered_prefixes(&storage).unwrap(), vec!("192.0.2.2/31"));
+
+        check_addresses(&mut storage, config::CheckLevel::Warn).unwrap();
+
+        assert_eq!(warnings(&storage, "other"), vec!(
+            "failed to find reverse zone for ip address: 192.0.2.2 (a.other.test.)",
+            "failed to find reverse zone for ip address: 192.0.2.3 (b.other.test.)"
+        ));
+    }
+
+    #[test]
+    fn levels() {
+        let conflicting = || {
+            let mut storage = storage();
+            storage.add_record_to(&"example".to_owned(), a("www.example.", [10, 0, 0, 1]));
+            storage.add_record_to(&"example".to_owned(), a("ftp.example.", [10, 0, 0, 1]));
+            storage
+        };
+
+        let mut ignored = conflicting();
+        check_addresses(&mut ignored, config::CheckLevel::Ignore).unwrap();
+        assert!(warnings(&ignored, "example").is_empty());
+
+        let mut warned = conflicting();
+        check_addresses(&mut warned, config::CheckLevel::Warn).unwrap();
+        assert_eq!(warnings(&warned, "example").len(), 1);
+
+        let mut failed = conflicting();
+        let result = check_addresses(&mut failed, config::CheckLevel::Fail);
+        assert!(result.err().unwrap().get_msg().contains("found 1 address problems"));
+        assert_eq!(warnings(&failed, "example").len(), 1);
+
+        let mut clean = storage();
+        check_addresses(&mut clean, config::CheckLevel::Fail).unwrap();
+    }
+}
@@ -1,16 +1,43 @@
+use std::cell::{RefCell};
+use std::path::{Path, PathBuf};
+
 use crate::config;
+use crate::dns;
+use crate::error;
+use crate::soa;
+use crate::template;
+
+fn to_ascii_domain(domain: String) -> error::Result<String> {
+    dns::domain_to_ascii(&domain).map_err(|label| error::RuntimeError::ConfigError(
+        format!("invalid domain label \"{}\" in \"{}\"", label, domain)
+    ))
+}
 
 pub struct ConfigContext {
+    base_directory: PathBuf,
     directory: String,
-    keys: config::KeysMap
+    idn_comments: bool,
+    address_conflicts: config::CheckLevel,
+    soa: Option<config::SoaDetail>,
+    nameservers: Vec<String>,
+    hooks: Vec<config::Hook>,
+    keys: config::KeysMap,
+    read_files: RefCell<Vec<PathBuf>>
 }
 
 impl ConfigContext {
 
-    pub fn new(config: config::Config) -> ConfigContext {
+    pub fn new(config: config::Config, file: &Path) -> ConfigContext {
         ConfigContext {
-            directory: config.directory.unwrap_or("".to_owned()),
-            keys: config.keys.unwrap_or(config::KeysMap::new())
+            base_directory: file.parent().map(|p| p.to_path_buf()).unwrap_or_default(),
+            directory: config.directory.unwrap_or_default(),
+            idn_comments: config.idn_comments.unwrap_or(false),
+            address_conflicts: config.address_conflicts.unwrap_or(config::CheckLevel::Warn),
+            soa: config.soa,
+            nameservers: config.nameservers.unwrap_or_default(),
+            hooks: config.hooks.unwrap_or_default(),
+            keys: config.keys.unwrap_or_default(),
+            read_files: RefCell::new(Vec::new())
         }
     }
 
@@ -18,27 +45,64 @@ impl ConfigContext {
         &self.directory
     }
 
-    pub fn find_key(&self, key: &String) -> Option<&String> {
-        self.keys.get(key)
+    pub fn get_idn_comments(&self) -> bool {
+        self.idn_comments
+    }
+
+    pub fn get_address_conflicts(&self) -> config::CheckLevel {
+        self.address_conflicts
+    }
+
+    /// resolves a path given in the config relative to the config file. the
+    /// path is remembered as a file read by the build
+    pub fn resolve_path(&self, path: &str) -> PathBuf {
+        let rtn = self.base_directory.join(path);
+
+        self.read_files.borrow_mut().push(rtn.clone());
+
+        rtn
+    }
+
+    /// takes the paths resolved so far
+    pub fn take_read_files(&self) -> Vec<PathBuf> {
+        self.read_files.take()
+    }
+
+    pub fn get_keys_ref(&self) -> &config::KeysMap {
+        &self.keys
     }
 }
 
 pub struct ZoneContext {
     name: String,
     domain: String,
+    unicode_domain: Option<String>,
     reverse_records: bool,
+    idn_comments: bool,
 
     ttl: usize,
 
+    soa: Option<config::SoaDetail>,
+    nameservers: Vec<String>,
+
+    /// the zone has no nameservers of its own and uses the ones of the config
+    config_nameservers: bool,
+
+    hooks: Vec<config::Hook>,
+
     keys: config::KeysMap
 }
 
 impl ZoneContext {
 
-    pub fn new(zone: config::Zone) -> ZoneContext {
-        ZoneContext {
-            name: zone.name.clone(),
-            domain: format!("{}.", zone.domain.unwrap_or(zone.name)),
+    pub fn new(config: &ConfigContext, zone: config::Zone) -> error::Result<ZoneContext> {
+        let given = format!("{}.", zone.domain.as_ref().unwrap_or(&zone.name));
+        let domain = to_ascii_domain(given.clone())?;
+
+        Ok(ZoneContext {
+            name: zone.name,
+            unicode_domain: if domain != given { Some(given) } else { None },
+            domain,
             reverse_records: match zone.reverse {
                 Some(which) => match which {
                     config::ReverseValue::Bool(rtn) => rtn,
@@ -46,24 +110,38 @@ impl ZoneContext {
                 },
                 None => false
             },
+            idn_comments: zone.idn_comments.unwrap_or_else(|| config.get_idn_comments()),
             ttl: zone.ttl.unwrap_or(604800),
-            keys: zone.keys.unwrap_or(config::KeysMap::new())
-        }
+            soa: soa::merge(config.soa.clone(), zone.soa),
+            config_nameservers: zone.nameservers.is_none(),
+            nameservers: zone.nameservers.unwrap_or_else(|| config.nameservers.clone()),
+            hooks: zone.hooks.unwrap_or_default().into_iter()
+                .chain(config.hooks.iter().cloned())
+                .collect(),
+            keys: zone.keys.unwrap_or_default()
+        })
     }
 
-    pub fn set_domain(&mut self, domain: String) -> () {
+    pub fn set_domain(&mut self, domain: String) {
         self.domain = domain;
+        self.unicode_domain = None;
     }
 
     pub fn get_domain(&self) -> String {
         self.domain.clone()
     }
 
-    pub fn with_domain(&self, domain: String) -> String {
-        if domain.ends_with(".") {
-            domain
+    pub fn get_unicode_domain_ref(&self) -> Option<&String> {
+        self.unicode_domain.as_ref()
+    }
+
+    pub fn with_domain(&self, domain: String) -> error::Result<String> {
+        let domain = to_ascii_domain(domain)?;
+
+        if domain.ends_with('.') {
+            Ok(domain)
         } else {
-            format!("{}.{}", domain, self.get_domain())
+            Ok(format!("{}.{}", domain, self.get_domain()))
         }
     }
 
@@ -71,6 +149,10 @@ impl ZoneContext {
         self.reverse_records
     }
 
+    pub fn get_idn_comments(&self) -> bool {
+        self.idn_comments
+    }
+
     pub fn get_ttl(&self) -> usize {
         self.ttl
     }
@@ -79,50 +161,105 @@ impl ZoneContext {
         &self.name
     }
 
+    pub fn get_soa_ref(&self) -> Option<&config::SoaDetail> {
+        self.soa.as_ref()
+    }
+
+    pub fn get_nameservers_ref(&self) -> &Vec<String> {
+        &self.nameservers
+    }
+
+    pub fn get_config_nameservers(&self) -> bool {
+        self.config_nameservers
+    }
+
     pub fn get_name(&self) -> String {
         self.name.clone()
     }
 
-    pub fn find_key(&self, key: &String) -> Option<&String> {
-        self.keys.get(key)
+    /// hooks of the zone followed by the hooks of the config
+    pub fn get_hooks_ref(&self) -> &Vec<config::Hook> {
+        &self.hooks
+    }
+
+    pub fn get_keys_ref(&self) -> &config::KeysMap {
+        &self.keys
     }
 }
 
 pub struct SubDomainContext {
     domain: String,
+    unicode_domain: Option<String>,
     reverse: bool,
+    index: Option<u32>,
+
+    keys: config::KeysMap
 }
 
 impl SubDomainContext {
 
-    pub fn new(zone: &ZoneContext, subdomain: config::SubDomain) -> SubDomainContext {
-        let mut domain: String = subdomain.domain.unwrap_or(subdomain.name.clone());
+    pub fn new(
+        config: &ConfigContext,
+        zone: &ZoneContext,
+        subdomain: config::SubDomain
+    ) -> error::Result<SubDomainContext> {
+        let keys = subdomain.keys.unwrap_or_default();
+        let given = template::Scope::new(vec!(&keys, zone.get_keys_ref(), config.get_keys_ref()))
+            .render(subdomain.domain.as_ref().unwrap_or(&subdomain.name))?;
+        let mut domain = to_ascii_domain(given.clone())?;
+        let mut unicode_domain = if domain != given { Some(given.clone()) } else { None };
 
-        if !domain.ends_with(".") {
+        if !domain.ends_with('.') {
             if domain.eq("@") {
                 domain = zone.get_domain();
+                unicode_domain = None;
             } else {
+                if unicode_domain.is_some() {
+                    unicode_domain = Some(format!(
+                        "{}.{}", given, zone.get_unicode_domain_ref().cloned().unwrap_or_else(|| zone.get_domain())
+                    ));
+                }
+
                 domain = format!("{}.{}", domain, zone.get_domain());
             }
         }
-        
-        SubDomainContext {
+
+        Ok(SubDomainContext {
             domain,
+            unicode_domain,
             reverse: match subdomain.reverse {
                 Some(which) => match which {
                     config::ReverseValue::Bool(rtn) => rtn,
                     config::ReverseValue::Str(_) => true
                 },
                 None => zone.get_reverse()
-            }
-        }
+            },
+            index: None,
+            keys
+        })
     }
 
     pub fn get_domain(&self) -> String {
         self.domain.clone()
     }
 
+    pub fn get_unicode_domain_ref(&self) -> Option<&String> {
+        self.unicode_domain.as_ref()
+    }
+
     pub fn get_reverse(&self) -> bool {
         self.reverse
     }
-}
\ No newline at end of file
+
+    pub fn set_index(&mut self, index: Option<u32>) {
+        self.index = index;
+    }
+
+    pub fn get_index(&self) -> Option<u32> {
+        self.index
+    }
+
+    pub fn get_keys_ref(&self) -> &config::KeysMap {
+        &self.keys
+    }
+}
@@ -0,0 +1,238 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::dns;
+
+/// a delegation of a child zone that is added to its parent zone
+struct Delegation {
+    parent: String,
+    origin: String,
+    records: Vec<dns::record::Record>
+}
+
+/// finds the zone with the longest origin that contains the given origin
+fn find_parent<'a>(origins: &'a [(String, String)], origin: &str) -> Option<&'a (String, String)> {
+    origins.iter()
+        .filter(|(_, parent)| parent != origin && dns::in_bailiwick(origin, parent))
+        .max_by_key(|(_, parent)| parent.len())
+}
+
+/// adds delegation NS records and glue to the parents of every zone in the
+/// storage whose parent zone is also defined. NS records the parent already
+/// has for a child are kept as is. name servers of a child that fall under a
+/// zone of the storage but have no address are reported as lame. warnings
+/// are kept with the child zone
+pub fn add_delegations(storage: &mut dns::ZoneStorage) {
+    let zones = storage.get_zones_ref();
+    let origins: Vec<(String, String)> = zones.values()
+        .map(|zone| (zone.get_name(), dns::normalize_domain(zone.get_origin_ref())))
+        .collect();
+    let mut addresses: HashMap<String, Vec<dns::record::Record>> = HashMap::new();
+    let mut existing: HashSet<(String, String)> = HashSet::new();
+
+    for zone in zones.values() {
+        for record in zone.get_records_ref() {
+            match record {
+                dns::record::Record::A {name, ..} |
+                dns::record::Record::Aaaa {name, ..} => {
+                    addresses.entry(dns::normalize_domain(name)).or_default().push(record.clone());
+                },
+                dns::record::Record::Ns {name, ..} => {
+                    existing.insert((zone.get_name(), dns::normalize_domain(name)));
+                },
+                _ => {}
+            }
+        }
+    }
+
+    let mut delegations: Vec<Delegation> = Vec::new();
+    let mut warnings: Vec<(String, String)> = Vec::new();
+
+    for (name, origin) in &origins {
+        let (parent, _) = match find_parent(&origins, origin) {
+            Some(parent) => parent,
+            None => continue
+        };
+        let nameservers: Vec<(usize, String)> = zones[name].get_records_ref().iter()
+            .filter_map(|record| match record {
+                dns::record::Record::Ns {name, ttl, domain} if dns::normalize_domain(name) == *origin =>
+                    Some((*ttl, dns::normalize_domain(domain))),
+                _ => None
+            })
+            .collect();
+
+        if nameservers.is_empty() {
+            warnings.push((name.clone(), format!(
+                "zone \"{}\" has no NS records to delegate from parent zone \"{}\"", name, parent
+            )));
+            continue;
+        }
+
+        for (_, nameserver) in &nameservers {
+            let known = origins.iter().any(|(_, o)| dns::in_bailiwick(nameserver, o));
+
+            if known && !addresses.contains_key(nameserver) {
+                warnings.push((name.clone(), format!(
+                    "lame delegation for zone \"{}\". name server {} has no address", name, nameserver
+                )));
+            }
+        }
+
+        if existing.contains(&(parent.clone(), origin.clone())) {
+            continue;
+        }
+
+        let mut records = Vec::new();
+
+        for (ttl, nameserver) in &nameservers {
+            records.push(dns::record::Record::Ns {
+                name: origin.clone(),
+                ttl: *ttl,
+                domain: nameserver.clone()
+            });
+        }
+
+        for (_, nameserver) in &nameservers {
+            if dns::in_bailiwick(nameserver, origin) {
+                if let Some(glue) = addresses.get(nameserver) {
+                    records.extend(glue.iter().cloned());
+                }
+            }
+        }
+
+        delegations.push(Delegation {
+            parent: parent.clone(),
+            origin: origin.clone(),
+            records
+        });
+    }
+
+    delegations.sort_by(|a, b| a.origin.cmp(&b.origin));
+    warnings.sort();
+
+    for (zone, warning) in warnings {
+        storage.add_warning(&[zone], warning);
+    }
+
+    for delegation in delegations {
+        storage.add_record_to(&delegation.parent, dns::record::Record::Blank);
+        storage.add_record_to(&delegation.parent, dns::record::Record::Comment(
+            format!("delegation for {}", delegation.origin)
+        ));
+
+        for record in delegation.records {
+            storage.add_record_to(&delegation.parent, record);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{Ipv4Addr};
+
+    use super::*;
+
+    fn ns(name: &str, domain: &str) -> dns::record::Record {
+        dns::record::Record::Ns { name: name.to_owned(), ttl: 3600, domain: domain.to_owned() }
+    }
+
+    fn a(name: &str, address: [u8; 4]) -> dns::record::Record {
+        dns::record::Record::A { name: name.to_owned(), ttl: 300, address: Ipv4Addr::from(address) }
+    }
+
+    fn storage(zones: Vec<(&str, Vec<dns::record::Record>)>) -> dns::ZoneStorage {
+        let mut storage = dns::ZoneStorage::new();
+
+        for (origin, records) in zones {
+            let name = origin.trim_end_matches('.').to_owned();
+            storage.add_zone(dns::zone::Zone::new(name.clone(), origin.to_owned()));
+
+            for record in records {
+                storage.add_record_to(&name, record);
+            }
+        }
+
+        storage
+    }
+
+    fn records(storage: &dns::ZoneStorage, zone: &str) -> Vec<String> {
+        storage.get_zones_ref()[zone].get_records_ref().iter().map(|record| record.to_string()).collect()
+    }
+
+    #[test]
+    fn longest_parent_gets_ns_and_glue() {
+        let mut storage = storage(vec!(
+            ("example.", vec!(ns("example.", "ns1.example."))),
+            ("dept.example.", vec!(ns("dept.example.", "ns1.example."))),
+            ("lab.dept.example.", vec!(
+                ns("lab.dept.example.", "ns1.lab.dept.example."),
+                ns("lab.dept.example.", "ns.other.test."),
+                a("ns1.lab.dept.example.", [10, 0, 0, 53]),
+                a("www.lab.dept.example.", [10, 0, 0, 80])
+            ))
+        ));
+        storage.add_record_to(&"example".to_owned(), a("ns1.example.", [10, 0, 0, 1]));
+
+        add_delegations(&mut storage);
+
+        let dept = records(&storage, "dept.example");
+        assert_eq!(&dept[1..], &[
+            dns::record::Record::Blank.to_string(),
+            dns::record::Record::Comment("delegation for lab.dept.example.".to_owned()).to_string(),
+            ns("lab.dept.example.", "ns1.lab.dept.example.").to_string(),
+            ns("lab.dept.example.", "ns.other.test.").to_string(),
+            a("ns1.lab.dept.example.", [10, 0, 0, 53]).to_string()
+        ]);
+
+        // the ns of dept is outside of dept, so example gets no glue for it
+        let example = records(&storage, "example");
+        assert!(example.contains(&ns("dept.example.", "ns1.example.").to_string()));
+        assert!(!example.iter().any(|record| record.contains("lab.dept.example.")));
+        assert_eq!(example.iter().filter(|record| record.contains("10.0.0.1")).count(), 1);
+    }
+
+    #[test]
+    fn out_of_bailiwick_ns_has_no_glue() {
+        let mut storage = storage(vec!(
+            ("example.", vec!()),
+            ("sub.example.", vec!(ns("sub.example.", "ns.other.test."))),
+            ("other.test.", vec!(ns("other.test.", "ns.other.test."), a("ns.other.test.", [192, 0, 2, 53])))
+        ));
+
+        add_delegations(&mut storage);
+
+        let example = records(&storage, "example");
+        assert!(example.contains(&ns("sub.example.", "ns.other.test.").to_string()));
+        assert!(!example.iter().any(|record| record.contains("192.0.2.53")));
+    }
+
+    #[test]
+    fn existing_delegations_are_kept() {
+        let mut storage = storage(vec!(
+            ("example.", vec!(ns("sub.example.", "ns9.example.net."))),
+            ("sub.example.", vec!(ns("sub.example.", "ns1.example.net.")))
+        ));
+
+        add_delegations(&mut storage);
+
+        assert_eq!(records(&storage, "example"), vec!(ns("sub.example.", "ns9.example.net.").to_string()));
+    }
+
+    #[test]
+    fn lame_delegations_are_reported() {
+        let mut storage = storage(vec!(
+            ("example.", vec!()),
+            ("sub.example.", vec!(ns("sub.example.", "ns1.sub.example."), ns("sub.example.", "ns.other.test."))),
+            ("empty.example.", vec!())
+        ));
+
+        add_delegations(&mut storage);
+
+        assert_eq!(storage.get_zones_ref()["sub.example"].get_warnings_ref(), &vec!(
+            "lame delegation for zone \"sub.example\". name server ns1.sub.example. has no address".to_owned()
+        ));
+        assert_eq!(storage.get_zones_ref()["empty.example"].get_warnings_ref(), &vec!(
+            "zone \"empty.example\" has no NS records to delegate from parent zone \"example\"".to_owned()
+        ));
+        assert!(storage.get_zones_ref()["example"].get_warnings_ref().is_empty());
+    }
+}
@@ -0,0 +1,338 @@
+use std::io::{Write};
+use std::os::unix::fs::{MetadataExt};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use log::{info, warn};
+
+use crate::error;
+
+/// name of the lock file that serializes runs writing to the same directory
+const LOCK_FILE_NAME: &str = ".dns-zones-builder.lock";
+
+static TMP_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// the utc date of a time given in seconds since the unix epoch as year,
+/// month and day
+pub fn civil_date(secs: u64) -> (i64, i64, i64) {
+    let days = (secs / 86400) as i64;
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    (year, month, day)
+}
+
+/// formats the current time as a utc timestamp that sorts by time, e.g.
+/// "20240131T235959.000000001Z"
+fn timestamp() -> String {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = now.as_secs();
+    let time = secs % 86400;
+    let (year, month, day) = civil_date(secs);
+
+    format!(
+        "{:04}{:02}{:02}T{:02}{:02}{:02}.{:09}Z",
+        year, month, day, time / 3600, (time % 3600) / 60, time % 60, now.subsec_nanos()
+    )
+}
+
+fn parent_directory(path: &Path) -> PathBuf {
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+        _ => PathBuf::from(".")
+    }
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name().unwrap_or_default().to_string_lossy().into_owned()
+}
+
+/// checks if the name is a backup created for the file with the prefix. the
+/// timestamp is checked so that backups of files sharing the prefix are not
+/// matched
+fn is_backup(name: &str, prefix: &str) -> bool {
+    match name.strip_prefix(prefix).and_then(|rest| rest.strip_suffix(".bak")) {
+        Some(stamp) => stamp.len() == 26 && stamp.starts_with(|c: char| c.is_ascii_digit()) && stamp.ends_with('Z'),
+        None => false
+    }
+}
+
+fn sync_directory(directory: &Path) -> error::Result<()> {
+    std::fs::File::open(directory)?.sync_all()?;
+
+    Ok(())
+}
+
+/// an exclusive lock on a directory. the lock is held until dropped
+pub struct DirectoryLock {
+    _file: std::fs::File
+}
+
+impl DirectoryLock {
+
+    /// blocks until the lock of the directory is acquired
+    pub fn acquire(directory: &Path) -> error::Result<DirectoryLock> {
+        let file = std::fs::OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(directory.join(LOCK_FILE_NAME))?;
+
+        if file.try_lock().is_err() {
+            info!("waiting for lock on {}", directory.display());
+            file.lock()?;
+        }
+
+        Ok(DirectoryLock { _file: file })
+    }
+}
+
+/// a file written to a temporary path next to its destination. the file is
+/// only moved into place when committed and is removed otherwise
+pub struct PendingFile {
+    path: PathBuf,
+    tmp_path: PathBuf
+}
+
+impl PendingFile {
+
+    /// writes and syncs the contents to a unique temporary file in the same
+    /// directory as the destination
+    pub fn create(path: &Path, contents: &[u8]) -> error::Result<PendingFile> {
+        PendingFile::create_in(&parent_directory(path), path, contents)
+    }
+
+    /// writes the contents to a unique temporary file in the temp directory
+    /// of the system. used to check files that are not written, the
+    /// destination directory is not touched. the file can not be committed
+    /// since it is not next to its destination
+    pub fn scratch(path: &Path, contents: &[u8]) -> error::Result<PendingFile> {
+        PendingFile::create_in(&std::env::temp_dir(), path, contents)
+    }
+
+    fn create_in(directory: &Path, path: &Path, contents: &[u8]) -> error::Result<PendingFile> {
+        let tmp_path = directory.join(format!(
+            ".{}.{}.{}.tmp",
+            file_name(path),
+            std::process::id(),
+            TMP_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        let mut file = std::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&tmp_path)?;
+        let pending = PendingFile {
+            path: path.to_path_buf(),
+            tmp_path
+        };
+
+        file.write_all(contents)?;
+        file.sync_all()?;
+
+        Ok(pending)
+    }
+
+    pub fn get_tmp_path_ref(&self) -> &Path {
+        &self.tmp_path
+    }
+
+    /// copies the permissions and ownership of the existing destination to
+    /// the temporary file
+    fn keep_metadata(&self) -> error::Result<()> {
+        let existing = match std::fs::metadata(&self.path) {
+            Ok(metadata) => metadata,
+            Err(_) => return Ok(())
+        };
+        let current = std::fs::metadata(&self.tmp_path)?;
+
+        std::fs::set_permissions(&self.tmp_path, existing.permissions())?;
+
+        if existing.uid() != current.uid() || existing.gid() != current.gid() {
+            if let Err(err) = std::os::unix::fs::chown(&self.tmp_path, Some(existing.uid()), Some(existing.gid())) {
+                warn!("failed to keep ownership of {}. {}", self.path.display(), err);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// copies the existing destination to a timestamped backup and removes
+    /// the oldest backups so that at most the given number remain
+    fn backup(&self, keep: usize) -> error::Result<()> {
+        if keep == 0 || !self.path.is_file() {
+            return Ok(());
+        }
+
+        let directory = parent_directory(&self.path);
+        let prefix = format!("{}.", file_name(&self.path));
+        let backup_path = directory.join(format!("{}{}.bak", prefix, timestamp()));
+
+        std::fs::copy(&self.path, &backup_path)?;
+
+        let mut backups: Vec<PathBuf> = std::fs::read_dir(&directory)?
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| is_backup(&file_name(path), &prefix))
+            .collect();
+
+        backups.sort();
+
+        while backups.len() > keep {
+            std::fs::remove_file(backups.remove(0))?;
+        }
+
+        Ok(())
+    }
+
+    /// moves the temporary file into place, keeping the permissions,
+    /// ownership and the requested number of backups of the previous file
+    pub fn commit(self, backups: usize) -> error::Result<()> {
+        self.keep_metadata()?;
+        self.backup(backups)?;
+
+        std::fs::rename(&self.tmp_path, &self.path)?;
+        sync_directory(&parent_directory(&self.path))?;
+
+        Ok(())
+    }
+}
+
+impl Drop for PendingFile {
+
+    fn drop(&mut self) {
+        if self.tmp_path.exists() {
+            let _ = std::fs::remove_file(&self.tmp_path);
+        }
+    }
+}
+
+/// writes a file atomically without keeping backups
+pub fn write(path: &Path, contents: &[u8]) -> error::Result<()> {
+    PendingFile::create(path, contents)?.commit(0)
+}
+
+/// puts the previous contents of a file back in place. the file is removed
+/// if it did not exist before
+pub fn restore(path: &Path, previous: Option<&[u8]>) -> error::Result<()> {
+    match previous {
+        Some(contents) => write(path, contents),
+        None => {
+            std::fs::remove_file(path)?;
+            sync_directory(&parent_directory(path))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn directory(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("dns-zones-builder-deploy-{}-{}", std::process::id(), name));
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path).unwrap();
+
+        path
+    }
+
+    fn names(directory: &Path) -> Vec<String> {
+        let mut rtn: Vec<String> = std::fs::read_dir(directory).unwrap()
+            .map(|entry| file_name(&entry.unwrap().path()))
+            .collect();
+
+        rtn.sort();
+
+        rtn
+    }
+
+    #[test]
+    fn civil_dates() {
+        assert_eq!(civil_date(0), (1970, 1, 1));
+        assert_eq!(civil_date(86399), (1970, 1, 1));
+        assert_eq!(civil_date(951782400), (2000, 2, 29));
+        assert_eq!(civil_date(951868800), (2000, 3, 1));
+        assert_eq!(civil_date(946684800), (2000, 1, 1));
+        assert_eq!(civil_date(946684799), (1999, 12, 31));
+        assert_eq!(civil_date(1709164800), (2024, 2, 29));
+    }
+
+    #[test]
+    fn backup_names() {
+        assert!(is_backup("example.20240131T235959.000000001Z.bak", "example."));
+        assert!(!is_backup("example.com.20240131T235959.000000001Z.bak", "example."));
+        assert!(!is_backup("example.old.bak", "example."));
+        assert!(!is_backup("example.20240131T235959.000000001Z", "example."));
+        assert!(!is_backup("other.20240131T235959.000000001Z.bak", "example."));
+    }
+
+    #[test]
+    fn backups_are_pruned() {
+        let directory = directory("prune");
+        let path = directory.join("example");
+        let other = directory.join("example.com");
+
+        write(&other, b"other").unwrap();
+
+        for contents in ["1", "2", "3", "4"] {
+            PendingFile::create(&path, contents.as_bytes()).unwrap().commit(2).unwrap();
+        }
+
+        let backups: Vec<String> = names(&directory).into_iter()
+            .filter(|name| is_backup(name, "example."))
+            .collect();
+
+        assert_eq!(backups.len(), 2);
+        assert_eq!(std::fs::read(directory.join(&backups[0])).unwrap(), b"2");
+        assert_eq!(std::fs::read(directory.join(&backups[1])).unwrap(), b"3");
+        assert_eq!(std::fs::read(&path).unwrap(), b"4");
+        assert_eq!(std::fs::read(&other).unwrap(), b"other");
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn pending_file_is_removed_on_drop() {
+        let directory = directory("drop");
+        let path = directory.join("example");
+        let pending = PendingFile::create(&path, b"contents").unwrap();
+        let tmp_path = pending.get_tmp_path_ref().to_path_buf();
+
+        assert!(tmp_path.is_file());
+        assert_eq!(tmp_path.parent(), Some(directory.as_path()));
+
+        drop(pending);
+
+        assert!(!tmp_path.exists());
+        assert!(!path.exists());
+        assert!(names(&directory).is_empty());
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn scratch_file_leaves_destination_alone() {
+        let directory = directory("scratch");
+        let path = directory.join("example");
+        let pending = PendingFile::scratch(&path, b"contents").unwrap();
+        let tmp_path = pending.get_tmp_path_ref().to_path_buf();
+
+        assert_eq!(std::fs::read(&tmp_path).unwrap(), b"contents");
+        assert_eq!(tmp_path.parent(), Some(std::env::temp_dir().as_path()));
+        assert!(names(&directory).is_empty());
+
+        drop(pending);
+
+        assert!(!tmp_path.exists());
+        assert!(!path.exists());
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
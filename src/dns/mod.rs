@@ -8,7 +8,9 @@ pub type FmtResult<T> = Result<T, fmt::Error>;
 pub mod record;
 pub mod zone;
 
-use crate::net::{Ipv4AddrCidr, Ipv6AddrCidr, ipv6_to_u128};
+use log::{warn};
+
+use crate::net::{IpAddrCidr, Ipv4AddrCidr, Ipv6AddrCidr, ipv6_to_u128};
 
 fn reverse_string(string: &mut String) {
     unsafe {
@@ -107,6 +109,56 @@ pub fn ipv6_reverse_prefix(ipv6: &Ipv6AddrCidr, include_suffix: bool) -> FmtResu
     Ok(rtn)
 }
 
+/// lowercases a domain and makes it absolute so that names can be compared
+pub fn normalize_domain(domain: &str) -> String {
+    let mut rtn = domain.to_ascii_lowercase();
+
+    if !rtn.ends_with('.') {
+        rtn.push('.');
+    }
+
+    rtn
+}
+
+/// checks if the domain is the same as or below the origin
+pub fn in_bailiwick(domain: &str, origin: &str) -> bool {
+    domain == origin || domain.ends_with(&format!(".{}", origin))
+}
+
+/// converts any unicode labels of the given domain into their A-label form
+/// using UTS #46 processing. ascii labels are left untouched so that special
+/// labels like "@", "*" or "_dmarc" are not mangled. on failure the offending
+/// label is returned
+pub fn domain_to_ascii(domain: &str) -> Result<String, String> {
+    if domain.is_ascii() {
+        return Ok(domain.to_owned());
+    }
+
+    let mut rtn = String::with_capacity(domain.len());
+    let mut first = true;
+
+    for label in domain.split('.') {
+        if !first {
+            rtn.push('.');
+        } else {
+            first = false;
+        }
+
+        if label.is_ascii() {
+            rtn.push_str(label);
+        } else {
+            match idna::domain_to_ascii(label) {
+                Ok(converted) if !converted.is_empty() && !converted.contains('.') => {
+                    rtn.push_str(&converted);
+                },
+                _ => return Err(label.to_owned())
+            }
+        }
+    }
+
+    Ok(rtn)
+}
+
 pub fn ip_reverse_string(ip: &IpAddr, include_suffix: bool) -> FmtResult<String> {
     match ip {
         IpAddr::V4(v4) => ipv4_reverse_string(v4, include_suffix),
@@ -114,10 +166,47 @@ pub fn ip_reverse_string(ip: &IpAddr, include_suffix: bool) -> FmtResult<String>
     }
 }
 
+/// parses the owner name of a PTR record back into the address it is for.
+/// only full addresses are accepted
+pub fn reverse_name_to_ip(name: &str) -> Option<IpAddr> {
+    let name = name.to_ascii_lowercase();
+
+    if let Some(prefix) = name.strip_suffix(".in-addr.arpa.") {
+        let mut octets: Vec<u8> = Vec::with_capacity(4);
+
+        for part in prefix.split('.').rev() {
+            octets.push(part.parse().ok()?);
+        }
+
+        if octets.len() == 4 {
+            return Some(IpAddr::from([octets[0], octets[1], octets[2], octets[3]]));
+        }
+    } else if let Some(prefix) = name.strip_suffix(".ip6.arpa.") {
+        let mut value: u128 = 0;
+        let mut count = 0;
+
+        for part in prefix.split('.').rev() {
+            if part.len() != 1 {
+                return None;
+            }
+
+            value = (value << 4) | u128::from_str_radix(part, 16).ok()?;
+            count += 1;
+        }
+
+        if count == 32 {
+            return Some(IpAddr::from(value.to_be_bytes()));
+        }
+    }
+
+    None
+}
+
 pub struct ZoneStorage {
     zones: HashMap<String, zone::Zone>,
     v4_reverse_zones: HashMap<String, Ipv4AddrCidr>,
     v6_reverse_zones: HashMap<String, Ipv6AddrCidr>,
+    unmatched_reverse: Vec<(IpAddr, String)>,
     current: Option<String>,
 }
 
@@ -128,6 +217,7 @@ impl ZoneStorage {
             zones: HashMap::new(),
             v4_reverse_zones: HashMap::new(),
             v6_reverse_zones: HashMap::new(),
+            unmatched_reverse: Vec::new(),
             current: None,
         }
     }
@@ -173,10 +263,53 @@ impl ZoneStorage {
         }
     }
 
+    /// the name of a reverse zone whose prefix shares addresses with the
+    /// given prefix
+    pub fn overlapping_reverse_zone(&self, prefix: &IpAddrCidr) -> Option<&String> {
+        match prefix {
+            IpAddrCidr::V4(v4) => self.v4_reverse_zones.iter()
+                .find(|(_, other)| other.overlaps(v4))
+                .map(|(name, _)| name),
+            IpAddrCidr::V6(v6) => self.v6_reverse_zones.iter()
+                .find(|(_, other)| other.overlaps(v6))
+                .map(|(name, _)| name)
+        }
+    }
+
     pub fn has_zone(&self, name: &String) -> bool {
         self.zones.contains_key(name)
     }
 
+    /// the name of the zone with the longest origin containing the domain
+    pub fn find_zone(&self, domain: &str) -> Option<&String> {
+        let domain = normalize_domain(domain);
+
+        self.zones.values()
+            .filter(|zone| in_bailiwick(&domain, &normalize_domain(zone.get_origin_ref())))
+            .max_by_key(|zone| zone.get_origin_ref().len())
+            .map(|zone| zone.get_name_ref())
+    }
+
+    /// logs a warning and keeps it with every given zone for the report
+    pub fn add_warning(&mut self, zones: &[String], warning: String) {
+        warn!("{}", warning);
+
+        for name in zones {
+            if let Some(zone) = self.zones.get_mut(name) {
+                zone.add_warning(warning.clone());
+            }
+        }
+    }
+
+    pub fn add_record_to(&mut self, zone: &String, record: record::Record) -> bool {
+        if let Some(found) = self.zones.get_mut(zone) {
+            found.add_record(record);
+            true
+        } else {
+            false
+        }
+    }
+
     pub fn add_record(&mut self, record: record::Record) -> bool {
         if let Some(name) = self.current.as_ref() {
             self.zones.get_mut(name).unwrap().add_record(record);
@@ -186,6 +319,32 @@ impl ZoneStorage {
         }
     }
 
+    /// adds an empty record to the current zone that is replaced later on.
+    /// returns the name of the zone and the index of the record
+    pub fn add_placeholder(&mut self) -> Option<(String, usize)> {
+        let name = self.current.as_ref()?;
+        let zone = self.zones.get_mut(name).unwrap();
+        let index = zone.get_records_ref().len();
+
+        zone.add_record(record::Record::Blank);
+
+        Some((name.clone(), index))
+    }
+
+    pub fn replace_record(&mut self, zone: &String, index: usize, record: record::Record) -> bool {
+        if let Some(found) = self.zones.get_mut(zone) {
+            found.replace_record(index, record)
+        } else {
+            false
+        }
+    }
+
+    fn add_unmatched_reverse(&mut self, addr: IpAddr, record: record::Record) {
+        if let record::Record::Ptr {domain, ..} = record {
+            self.unmatched_reverse.push((addr, domain));
+        }
+    }
+
     pub fn add_v4_reverse_record(&mut self, addr: &Ipv4Addr, record: record::Record) -> bool {
         for (name, ip_cidr) in self.v4_reverse_zones.iter() {
             if ip_cidr.in_range(addr) {
@@ -194,6 +353,8 @@ impl ZoneStorage {
             }
         }
 
+        self.add_unmatched_reverse(IpAddr::V4(*addr), record);
+
         false
     }
 
@@ -205,14 +366,49 @@ impl ZoneStorage {
             }
         }
 
+        self.add_unmatched_reverse(IpAddr::V6(*addr), record);
+
         false
     }
 
+    /// addresses, and the domain they point to, that requested a reverse
+    /// record but are not inside of any reverse zone
+    pub fn get_unmatched_reverse_ref(&self) -> &Vec<(IpAddr, String)> {
+        &self.unmatched_reverse
+    }
+
     pub fn reserve(&mut self, additional: usize) {
         self.zones.reserve(additional);
     }
 
+    pub fn get_zones_ref(&self) -> &HashMap<String, zone::Zone> {
+        &self.zones
+    }
+
+    pub fn get_zones_mut(&mut self) -> &mut HashMap<String, zone::Zone> {
+        &mut self.zones
+    }
+
     pub fn into_inner(self) -> HashMap<String, zone::Zone> {
         self.zones
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unicode_labels() {
+        assert_eq!(domain_to_ascii("café").unwrap(), "xn--caf-dma");
+        assert_eq!(domain_to_ascii("www.café.example").unwrap(), "www.xn--caf-dma.example");
+        assert_eq!(domain_to_ascii("_dmarc.bücher.example.").unwrap(), "_dmarc.xn--bcher-kva.example.");
+        assert_eq!(domain_to_ascii("@").unwrap(), "@");
+    }
+
+    #[test]
+    fn invalid_labels() {
+        assert_eq!(domain_to_ascii("www.xn--café.example"), Err("xn--café".to_owned()));
+        assert_eq!(domain_to_ascii("a\u{200d}é.example"), Err("a\u{200d}é".to_owned()));
+    }
+}
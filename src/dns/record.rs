@@ -1,8 +1,257 @@
 use std::{fmt};
+use std::convert::{TryFrom};
 use std::net::{Ipv4Addr, Ipv6Addr};
 
 type RecordName = String;
 
+/// max number of bytes allowed in a single character-string
+const CHARACTER_STRING_LEN: usize = 255;
+
+/// the data of a TXT record as a list of strings. strings longer than 255
+/// bytes are split into multiple character-strings when displayed and two
+/// values are equal if their joined data is equal, regardless of how they
+/// were split
+#[derive(Debug, Clone)]
+pub struct TxtData {
+    strings: Vec<String>
+}
+
+impl TxtData {
+
+    pub fn new(value: String) -> TxtData {
+        TxtData {
+            strings: vec!(value)
+        }
+    }
+
+    pub fn from_strings(strings: Vec<String>) -> TxtData {
+        TxtData { strings }
+    }
+
+    /// parses TXT data in the presentation format written by Display. the
+    /// character-strings are joined into a single value
+    pub fn parse(data: &str) -> Option<TxtData> {
+        let mut joined: Vec<u8> = Vec::with_capacity(data.len());
+        let mut bytes = data.bytes();
+
+        while let Some(byte) = bytes.next() {
+            match byte {
+                b' ' | b'\t' => continue,
+                b'"' => {},
+                _ => return None
+            }
+
+            loop {
+                match bytes.next()? {
+                    b'"' => break,
+                    b'\\' => match bytes.next()? {
+                        digit @ b'0'..=b'9' => {
+                            let mut value = u32::from(digit - b'0');
+
+                            for _ in 0..2 {
+                                match bytes.next()? {
+                                    digit @ b'0'..=b'9' => value = value * 10 + u32::from(digit - b'0'),
+                                    _ => return None
+                                }
+                            }
+
+                            joined.push(u8::try_from(value).ok()?);
+                        },
+                        escaped => joined.push(escaped)
+                    },
+                    byte => joined.push(byte)
+                }
+            }
+        }
+
+        String::from_utf8(joined).ok().map(TxtData::new)
+    }
+
+    pub fn joined(&self) -> String {
+        self.strings.concat()
+    }
+
+    pub fn character_strings(&self) -> Vec<&[u8]> {
+        let mut rtn = Vec::with_capacity(self.strings.len());
+
+        for string in &self.strings {
+            if string.is_empty() {
+                rtn.push(string.as_bytes());
+            } else {
+                rtn.extend(string.as_bytes().chunks(CHARACTER_STRING_LEN));
+            }
+        }
+
+        rtn
+    }
+}
+
+impl PartialEq for TxtData {
+
+    fn eq(&self, other: &Self) -> bool {
+        self.strings.iter().flat_map(|s| s.bytes()).eq(
+            other.strings.iter().flat_map(|s| s.bytes())
+        )
+    }
+
+}
+
+impl Eq for TxtData {}
+
+impl fmt::Display for TxtData {
+
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut first = true;
+
+        for character_string in self.character_strings() {
+            if first {
+                first = false;
+            } else {
+                write!(f, " ")?;
+            }
+
+            write!(f, "\"")?;
+
+            for byte in character_string {
+                match byte {
+                    b'"' | b'\\' => write!(f, "\\{}", *byte as char)?,
+                    0x20..=0x7e => write!(f, "{}", *byte as char)?,
+                    _ => write!(f, "\\{:0>3}", byte)?
+                }
+            }
+
+            write!(f, "\"")?;
+        }
+
+        Ok(())
+    }
+
+}
+
+/// service parameters of a SVCB or HTTPS record. displayed in the RFC 9460
+/// presentation format ordered by key number
+#[derive(Debug, Clone, Default)]
+pub struct SvcParams {
+    pub mandatory: Vec<String>,
+    pub alpn: Vec<String>,
+    pub no_default_alpn: bool,
+    pub port: Option<u16>,
+    pub ipv4hint: Vec<Ipv4Addr>,
+    pub ech: Option<String>,
+    pub ipv6hint: Vec<Ipv6Addr>
+}
+
+impl SvcParams {
+
+    pub fn is_empty(&self) -> bool {
+        self.mandatory.is_empty() &&
+        self.alpn.is_empty() &&
+        !self.no_default_alpn &&
+        self.port.is_none() &&
+        self.ipv4hint.is_empty() &&
+        self.ech.is_none() &&
+        self.ipv6hint.is_empty()
+    }
+
+    /// checks the parameters against the rules of RFC 9460 for the given
+    /// priority and sorts the mandatory keys into key number order
+    pub fn validate(&mut self, priority: u16) -> Result<(), String> {
+        const KEYS: [&str; 7] = ["mandatory", "alpn", "no-default-alpn", "port", "ipv4hint", "ech", "ipv6hint"];
+
+        if priority == 0 {
+            return if self.is_empty() {
+                Ok(())
+            } else {
+                Err("alias mode (priority 0) does not allow service parameters".to_owned())
+            };
+        }
+
+        for id in &self.alpn {
+            if id.is_empty() || id.len() > 255 || !id.bytes().all(|b| b.is_ascii_graphic() && b != b',' && b != b'\\' && b != b'"') {
+                return Err(format!("invalid alpn id given: \"{}\"", id));
+            }
+        }
+
+        if self.no_default_alpn && self.alpn.is_empty() {
+            return Err("no-default-alpn requires alpn to be given".to_owned());
+        }
+
+        if let Some(ech) = &self.ech {
+            if base64::Engine::decode(&base64::engine::general_purpose::STANDARD, ech).is_err() {
+                return Err(format!("ech is not valid base64: \"{}\"", ech));
+            }
+        }
+
+        for key in &self.mandatory {
+            let present = match key.as_str() {
+                "alpn" => !self.alpn.is_empty(),
+                "no-default-alpn" => self.no_default_alpn,
+                "port" => self.port.is_some(),
+                "ipv4hint" => !self.ipv4hint.is_empty(),
+                "ech" => self.ech.is_some(),
+                "ipv6hint" => !self.ipv6hint.is_empty(),
+                "mandatory" => return Err("mandatory must not list itself".to_owned()),
+                _ => return Err(format!("unknown key in mandatory: \"{}\"", key))
+            };
+
+            if !present {
+                return Err(format!("mandatory key \"{}\" is not given", key));
+            }
+        }
+
+        self.mandatory.sort_by_key(|key| KEYS.iter().position(|k| k == key));
+
+        if self.mandatory.windows(2).any(|pair| pair[0] == pair[1]) {
+            return Err("mandatory contains duplicate keys".to_owned());
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for SvcParams {
+
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fn join<T: fmt::Display>(list: &[T]) -> String {
+            list.iter().map(|v| v.to_string()).collect::<Vec<String>>().join(",")
+        }
+
+        let mut params: Vec<String> = Vec::with_capacity(7);
+
+        if !self.mandatory.is_empty() {
+            params.push(format!("mandatory={}", join(&self.mandatory)));
+        }
+
+        if !self.alpn.is_empty() {
+            params.push(format!("alpn={}", join(&self.alpn)));
+        }
+
+        if self.no_default_alpn {
+            params.push("no-default-alpn".to_owned());
+        }
+
+        if let Some(port) = self.port {
+            params.push(format!("port={}", port));
+        }
+
+        if !self.ipv4hint.is_empty() {
+            params.push(format!("ipv4hint={}", join(&self.ipv4hint)));
+        }
+
+        if let Some(ech) = &self.ech {
+            params.push(format!("ech={}", ech));
+        }
+
+        if !self.ipv6hint.is_empty() {
+            params.push(format!("ipv6hint={}", join(&self.ipv6hint)));
+        }
+
+        write!(f, "{}", params.join(" "))
+    }
+
+}
+
+#[derive(Clone)]
 pub enum Record {
     Soa {
         name: RecordName,
@@ -53,7 +302,7 @@ pub enum Record {
     Txt {
         name: RecordName,
         ttl: usize,
-        value: String
+        value: TxtData
     },
 
     Ptr {
@@ -62,11 +311,71 @@ pub enum Record {
         domain: String
     },
 
+    Tlsa {
+        name: RecordName,
+        ttl: usize,
+        usage: u8,
+        selector: u8,
+        matching: u8,
+        data: String
+    },
+
+    Sshfp {
+        name: RecordName,
+        ttl: usize,
+        algorithm: u8,
+        fingerprint_type: u8,
+        fingerprint: String
+    },
+
+    Svcb {
+        name: RecordName,
+        ttl: usize,
+        priority: u16,
+        target: String,
+        params: SvcParams
+    },
+
+    Https {
+        name: RecordName,
+        ttl: usize,
+        priority: u16,
+        target: String,
+        params: SvcParams
+    },
+
+    Comment(String),
+
     Blank
 }
 
+impl Record {
+
+    /// the type of the record as written in a zone file. comments and blank
+    /// lines have no type
+    pub fn get_type(&self) -> Option<&'static str> {
+        match self {
+            Record::Soa {..} => Some("SOA"),
+            Record::Ns {..} => Some("NS"),
+            Record::A {..} => Some("A"),
+            Record::Aaaa {..} => Some("AAAA"),
+            Record::Mx {..} => Some("MX"),
+            Record::Cname {..} => Some("CNAME"),
+            Record::Txt {..} => Some("TXT"),
+            Record::Ptr {..} => Some("PTR"),
+            Record::Tlsa {..} => Some("TLSA"),
+            Record::Sshfp {..} => Some("SSHFP"),
+            Record::Svcb {..} => Some("SVCB"),
+            Record::Https {..} => Some("HTTPS"),
+            Record::Comment(_) |
+            Record::Blank => None
+        }
+    }
+}
+
 impl fmt::Display for Record {
 
+    #[allow(clippy::borrow_deref_ref)]
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match &*self {
             Record::Soa {name, ttl, domain, email, serial, refresh, retry, expire, nct} =>
@@ -93,9 +402,130 @@ impl fmt::Display for Record {
                 write!(f, "{name}\t{ttl}\tIN\tTXT\t{value}", name=name, ttl=ttl, value=value),
             Record::Ptr {name, ttl, domain} =>
                 write!(f, "{name}\t{ttl}\tIN\tPTR\t{domain}", name=name, ttl=ttl, domain=domain),
+            Record::Tlsa {name, ttl, usage, selector, matching, data} =>
+                write!(f, "{name}\t{ttl}\tIN\tTLSA\t{usage} {selector} {matching} {data}",
+                    name=name, ttl=ttl, usage=usage, selector=selector, matching=matching, data=data
+                ),
+            Record::Sshfp {name, ttl, algorithm, fingerprint_type, fingerprint} =>
+                write!(f, "{name}\t{ttl}\tIN\tSSHFP\t{algorithm} {fingerprint_type} {fingerprint}",
+                    name=name, ttl=ttl, algorithm=algorithm, fingerprint_type=fingerprint_type, fingerprint=fingerprint
+                ),
+            Record::Svcb {name, ttl, priority, target, params} =>
+                if params.is_empty() {
+                    write!(f, "{name}\t{ttl}\tIN\tSVCB\t{priority}\t{target}", name=name, ttl=ttl, priority=priority, target=target)
+                } else {
+                    write!(f, "{name}\t{ttl}\tIN\tSVCB\t{priority}\t{target}\t{params}", name=name, ttl=ttl, priority=priority, target=target, params=params)
+                },
+            Record::Https {name, ttl, priority, target, params} =>
+                if params.is_empty() {
+                    write!(f, "{name}\t{ttl}\tIN\tHTTPS\t{priority}\t{target}", name=name, ttl=ttl, priority=priority, target=target)
+                } else {
+                    write!(f, "{name}\t{ttl}\tIN\tHTTPS\t{priority}\t{target}\t{params}", name=name, ttl=ttl, priority=priority, target=target, params=params)
+                },
+            Record::Comment(comment) =>
+                write!(f, "; {}", comment),
             Record::Blank =>
                 write!(f, "")
         }
     }
     
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(strings: &[&str]) -> TxtData {
+        TxtData::from_strings(strings.iter().map(|s| s.to_string()).collect())
+    }
+
+    #[test]
+    fn split_and_unsplit_are_equal() {
+        assert_eq!(strings(&["a", "b"]), TxtData::new("ab".to_owned()));
+        assert_ne!(strings(&["a", "b"]), TxtData::new("a b".to_owned()));
+    }
+
+    #[test]
+    fn long_values_are_split() {
+        let value = "x".repeat(300);
+        let data = TxtData::new(value.clone());
+
+        assert_eq!(data.character_strings().len(), 2);
+        assert_eq!(data.to_string(), format!("\"{}\" \"{}\"", &value[..255], &value[255..]));
+    }
+
+    #[test]
+    fn escaping() {
+        assert_eq!(strings(&["say \"hi\"; \\", ""]).to_string(), "\"say \\\"hi\\\"; \\\\\" \"\"");
+        assert_eq!(TxtData::new("é".to_owned()).to_string(), "\"\\195\\169\"");
+    }
+
+    #[test]
+    fn parse() {
+        let data = strings(&["say \"hi\"; \\", "", "zürich"]);
+        assert_eq!(TxtData::parse(&data.to_string()), Some(data));
+
+        let long = TxtData::new("é".repeat(200));
+        assert_eq!(TxtData::parse(&long.to_string()), Some(long));
+
+        assert_eq!(TxtData::parse("\"a\" \"b\""), Some(TxtData::new("ab".to_owned())));
+        assert_eq!(TxtData::parse("\"unclosed"), None);
+        assert_eq!(TxtData::parse("bare"), None);
+        assert_eq!(TxtData::parse("\"\\256\""), None);
+    }
+
+    #[test]
+    fn svc_params_order() {
+        let mut params = SvcParams {
+            mandatory: vec!("ipv6hint".to_owned(), "port".to_owned(), "alpn".to_owned()),
+            alpn: vec!("h2".to_owned(), "h3".to_owned()),
+            no_default_alpn: true,
+            port: Some(8443),
+            ipv4hint: vec!(Ipv4Addr::new(192, 0, 2, 1), Ipv4Addr::new(192, 0, 2, 2)),
+            ech: Some("AAAA".to_owned()),
+            ipv6hint: vec!("2001:db8::1".parse().unwrap())
+        };
+
+        params.validate(1).unwrap();
+
+        assert_eq!(params.to_string(), concat!(
+            "mandatory=alpn,port,ipv6hint alpn=h2,h3 no-default-alpn port=8443 ",
+            "ipv4hint=192.0.2.1,192.0.2.2 ech=AAAA ipv6hint=2001:db8::1"
+        ));
+    }
+
+    #[test]
+    fn svc_params_mandatory() {
+        let params = |mandatory: &[&str]| SvcParams {
+            mandatory: mandatory.iter().map(|k| k.to_string()).collect(),
+            port: Some(443),
+            ..SvcParams::default()
+        };
+
+        assert!(params(&["port"]).validate(1).is_ok());
+        assert_eq!(params(&["alpn"]).validate(1), Err("mandatory key \"alpn\" is not given".to_owned()));
+        assert_eq!(params(&["mandatory"]).validate(1), Err("mandatory must not list itself".to_owned()));
+        assert_eq!(params(&["key65000"]).validate(1), Err("unknown key in mandatory: \"key65000\"".to_owned()));
+        assert_eq!(params(&["port", "port"]).validate(1), Err("mandatory contains duplicate keys".to_owned()));
+    }
+
+    #[test]
+    fn svc_params_alias_mode() {
+        assert!(SvcParams::default().validate(0).is_ok());
+
+        let port = SvcParams { port: Some(443), ..SvcParams::default() };
+        assert_eq!(port.clone().validate(0), Err("alias mode (priority 0) does not allow service parameters".to_owned()));
+        assert!(port.clone().validate(1).is_ok());
+    }
+
+    #[test]
+    fn svc_params_values() {
+        let alpn = |id: &str| SvcParams { alpn: vec!(id.to_owned()), ..SvcParams::default() };
+
+        assert!(alpn("h2").validate(1).is_ok());
+        assert!(alpn("").validate(1).is_err());
+        assert!(alpn("h2,h3").validate(1).is_err());
+        assert!(SvcParams { no_default_alpn: true, ..SvcParams::default() }.validate(1).is_err());
+        assert!(SvcParams { ech: Some("not base64!".to_owned()), ..SvcParams::default() }.validate(1).is_err());
+    }
+}
@@ -7,7 +7,10 @@ pub struct Zone {
 
     origin: String,
 
-    records: Vec<record::Record>
+    records: Vec<record::Record>,
+
+    /// problems found with the records of the zone, kept for the report
+    warnings: Vec<String>
 }
 
 impl Zone {
@@ -19,7 +22,8 @@ impl Zone {
         Zone {
             name,
             origin,
-            records: vec!()
+            records: vec!(),
+            warnings: vec!()
         }
     }
 
@@ -35,9 +39,66 @@ impl Zone {
         &self.origin
     }
 
+    pub fn get_records_ref(&self) -> &Vec<record::Record> {
+        &self.records
+    }
+
+    #[allow(clippy::useless_conversion)]
     pub fn add_record(&mut self, record: record::Record) {
         self.records.push(record.into());
     }
+
+    pub fn get_warnings_ref(&self) -> &Vec<String> {
+        &self.warnings
+    }
+
+    pub fn add_warning(&mut self, warning: String) {
+        self.warnings.push(warning);
+    }
+
+    /// moves the first soa record to the start of the zone and returns the
+    /// number of soa records in the zone
+    pub fn move_soa_first(&mut self) -> usize {
+        let count = self.records.iter()
+            .filter(|r| matches!(r, record::Record::Soa {..}))
+            .count();
+
+        if let Some(index) = self.records.iter().position(|r| matches!(r, record::Record::Soa {..})) {
+            let soa = self.records.remove(index);
+            self.records.insert(0, soa);
+        }
+
+        count
+    }
+
+    /// serial of the first soa record in the zone
+    pub fn get_serial(&self) -> Option<u32> {
+        self.records.iter().find_map(|r| match r {
+            record::Record::Soa {serial, ..} => Some(*serial),
+            _ => None
+        })
+    }
+
+    /// sets the serial of the first soa record in the zone
+    pub fn set_serial(&mut self, value: u32) -> bool {
+        for r in self.records.iter_mut() {
+            if let record::Record::Soa {serial, ..} = r {
+                *serial = value;
+                return true;
+            }
+        }
+
+        false
+    }
+
+    pub fn replace_record(&mut self, index: usize, record: record::Record) -> bool {
+        if let Some(existing) = self.records.get_mut(index) {
+            *existing = record;
+            true
+        } else {
+            false
+        }
+    }
 }
 
 impl fmt::Display for Zone {
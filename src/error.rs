@@ -2,10 +2,15 @@ use std::{fmt};
 use std::convert::{From};
 use std::ffi::{OsString};
 
+use crate::net;
+
 pub type Result<T> = std::result::Result<T, RuntimeError>;
 
+#[derive(Debug)]
 pub enum RuntimeError {
     Error(String),
+    ConfigError(String),
+    InvalidArgument(String),
     InvalidFile(OsString),
     FileNotFound(String),
 
@@ -15,14 +20,19 @@ pub enum RuntimeError {
     JsonError(serde_json::Error),
     YamlError(serde_yaml::Error),
 
-    IoError(std::io::Error)
+    IoError(std::io::Error),
+
+    NetError(net::error::Error)
 }
 
 impl RuntimeError {
 
+    #[allow(clippy::borrow_deref_ref, clippy::useless_format)]
     pub fn get_msg(&self) -> String {
         match &*self {
             RuntimeError::Error(msg) => msg.clone(),
+            RuntimeError::ConfigError(msg) => format!("config error: {}", msg),
+            RuntimeError::InvalidArgument(msg) => format!("invalid argument. {}", msg),
             RuntimeError::InvalidFile(arg) => format!("configuration file is not a file: {:?}", arg),
             RuntimeError::FileNotFound(file) => format!("file not found: {}", file),
             RuntimeError::UnknownFileExtension => format!("unknown file extension given"),
@@ -50,20 +60,25 @@ impl RuntimeError {
                     format!("yaml error {:?}", err)
                 }
             },
-            RuntimeError::IoError(err) => format!("{:?}", err)
+            RuntimeError::IoError(err) => format!("{:?}", err),
+            RuntimeError::NetError(err) => err.get_msg()
         }
     }
     
+    #[allow(clippy::borrow_deref_ref)]
     pub fn get_code(&self) -> i32 {
         match &*self {
             RuntimeError::Error(_) => 1,
+            RuntimeError::ConfigError(_) => 1,
+            RuntimeError::InvalidArgument(_) => 1,
             RuntimeError::InvalidFile(_) => 1,
             RuntimeError::FileNotFound(_) => 1,
             RuntimeError::UnknownFileExtension => 1,
             RuntimeError::InvalidFileExtension(_) => 1,
             RuntimeError::JsonError(_) => 1,
             RuntimeError::YamlError(_) => 1,
-            RuntimeError::IoError(_) => 1
+            RuntimeError::IoError(_) => 1,
+            RuntimeError::NetError(_) => 1
         }
     }
 }
@@ -92,6 +107,12 @@ impl From<std::io::Error> for RuntimeError {
     }
 }
 
+impl From<net::error::Error> for RuntimeError {
+    fn from(error: net::error::Error) -> Self {
+        RuntimeError::NetError(error)
+    }
+}
+
 impl From<std::fmt::Error> for RuntimeError {
     fn from(error: std::fmt::Error) -> Self {
         RuntimeError::Error(format!("{:?}", error))
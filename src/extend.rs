@@ -0,0 +1,345 @@
+use std::collections::{HashMap};
+
+use log::{warn};
+
+use crate::config;
+use crate::error;
+use crate::soa;
+
+fn empty_template() -> config::ZoneTemplate {
+    config::ZoneTemplate {
+        ttl: None,
+        reverse: None,
+        keys: None,
+        soa: None,
+        nameservers: None,
+        extends: None,
+        subdomains: Vec::new()
+    }
+}
+
+pub fn merge_keys(base: &mut Option<config::KeysMap>, overrides: Option<config::KeysMap>) {
+    if let Some(overrides) = overrides {
+        base.get_or_insert_with(HashMap::new).extend(overrides);
+    }
+}
+
+/// checks if two records of a subdomain have the same type and views
+fn same_kind(a: &config::SubDomainRecord, b: &config::SubDomainRecord) -> bool {
+    a.record.get_type() == b.record.get_type() && a.views == b.views
+}
+
+/// merges subdomains into the subdomains of a template. subdomains are
+/// matched by name. records of a matching subdomain are appended unless the
+/// overriding subdomain uses the Replace merge mode. records repeating or
+/// shadowing a record of the same type are added to the warnings
+pub fn merge_subdomains(
+    base: &mut Vec<config::SubDomain>,
+    overrides: Vec<config::SubDomain>,
+    owner: &str,
+    source: &str,
+    warnings: &mut Vec<String>
+) {
+    for mut subdomain in overrides {
+        let existing = match base.iter_mut().find(|s| s.name == subdomain.name) {
+            Some(existing) => existing,
+            None => {
+                base.push(subdomain);
+                continue;
+            }
+        };
+
+        if subdomain.merge == Some(config::SubDomainMerge::Replace) {
+            if !existing.records.is_empty() {
+                warnings.push(format!(
+                    "subdomain \"{}\" of {} shadows {} record(s) from {}",
+                    subdomain.name, owner, existing.records.len(), source
+                ));
+            }
+
+            *existing = subdomain;
+        } else {
+            existing.domain = subdomain.domain.or(existing.domain.take());
+            existing.views = subdomain.views.or(existing.views.take());
+            existing.merge = subdomain.merge.or(existing.merge.take());
+            existing.reverse = subdomain.reverse.or(existing.reverse.take());
+            existing.range = subdomain.range.or(existing.range.take());
+            merge_keys(&mut existing.keys, subdomain.keys);
+
+            let duplicates = subdomain.records.iter()
+                .filter(|record| existing.records.contains(record))
+                .count();
+
+            if duplicates > 0 {
+                warnings.push(format!(
+                    "subdomain \"{}\" of {} repeats {} record(s) from {}",
+                    subdomain.name, owner, duplicates, source
+                ));
+            }
+
+            let mut shadowed: Vec<&'static str> = subdomain.records.iter()
+                .filter(|record| !existing.records.contains(record))
+                .filter(|record| existing.records.iter().any(|e| same_kind(e, record)))
+                .map(|record| record.record.get_type())
+                .collect();
+            shadowed.sort_unstable();
+            shadowed.dedup();
+
+            for r#type in shadowed {
+                let count = existing.records.iter().filter(|e| e.record.get_type() == r#type).count();
+
+                warnings.push(format!(
+                    "subdomain \"{}\" of {} shadows {} {} record(s) from {}. both are kept, use the Replace merge mode to drop them",
+                    subdomain.name, owner, count, r#type, source
+                ));
+            }
+
+            existing.records.append(&mut subdomain.records);
+        }
+    }
+}
+
+/// merges a template, or the settings of a zone, on top of a base template.
+/// values given by the overriding template win
+fn merge(
+    base: &mut config::ZoneTemplate,
+    overrides: config::ZoneTemplate,
+    owner: &str,
+    source: &str,
+    warnings: &mut Vec<String>
+) {
+    base.ttl = overrides.ttl.or(base.ttl);
+    base.reverse = overrides.reverse.or(base.reverse.take());
+    merge_keys(&mut base.keys, overrides.keys);
+    base.soa = soa::merge(base.soa.take(), overrides.soa);
+    base.nameservers = overrides.nameservers.or(base.nameservers.take());
+    merge_subdomains(&mut base.subdomains, overrides.subdomains, owner, source, warnings);
+}
+
+fn template_source(names: &[String]) -> String {
+    format!("template {}", names.join(", "))
+}
+
+/// adds a template after every template it extends to the merge order.
+/// templates already in the order are skipped so that a template extended
+/// by more than one template is only merged once
+fn linearize(
+    name: &String,
+    templates: &config::TemplatesMap,
+    visiting: &mut Vec<String>,
+    order: &mut Vec<String>
+) -> error::Result<()> {
+    let template = templates.get(name).ok_or_else(|| error::RuntimeError::ConfigError(
+        format!("unknown zone template requested: \"{}\"", name)
+    ))?;
+
+    if visiting.contains(name) {
+        return Err(error::RuntimeError::ConfigError(format!(
+            "zone template loop detected: {} -> {}", visiting.join(" -> "), name
+        )));
+    }
+
+    if order.contains(name) {
+        return Ok(());
+    }
+
+    visiting.push(name.clone());
+
+    for parent in template.extends.iter().flatten() {
+        linearize(parent, templates, visiting, order)?;
+    }
+
+    visiting.pop();
+    order.push(name.clone());
+
+    Ok(())
+}
+
+/// merges the given templates, and every template they extend, into a
+/// single template. extended templates are merged before the templates
+/// extending them
+fn resolve_list(
+    names: &[String],
+    templates: &config::TemplatesMap,
+    warnings: &mut Vec<String>
+) -> error::Result<config::ZoneTemplate> {
+    let mut order = Vec::new();
+
+    for name in names {
+        linearize(name, templates, &mut Vec::new(), &mut order)?;
+    }
+
+    let mut rtn = empty_template();
+
+    for (index, name) in order.iter().enumerate() {
+        let mut template = templates[name].clone();
+        template.extends = None;
+
+        merge(&mut rtn, template, &format!("template \"{}\"", name), &template_source(&order[..index]), warnings);
+    }
+
+    Ok(rtn)
+}
+
+/// applies the templates a zone extends to the zone. templates are merged in
+/// the order given and the zone itself is merged last. repeated and shadowed
+/// records are logged as warnings
+pub fn resolve_zones(
+    templates: Option<config::TemplatesMap>,
+    zones: Vec<config::Zone>
+) -> error::Result<Vec<config::Zone>> {
+    let templates = templates.unwrap_or_default();
+    let mut rtn = Vec::with_capacity(zones.len());
+    let mut warnings = Vec::new();
+
+    for mut zone in zones {
+        if let Some(extends) = zone.extends.take() {
+            let mut base = resolve_list(&extends, &templates, &mut warnings)?;
+
+            merge(&mut base, config::ZoneTemplate {
+                ttl: zone.ttl.take(),
+                reverse: zone.reverse.take(),
+                keys: zone.keys.take(),
+                soa: zone.soa.take(),
+                nameservers: zone.nameservers.take(),
+                extends: None,
+                subdomains: std::mem::take(&mut zone.subdomains)
+            }, &format!("zone \"{}\"", zone.name), &template_source(&extends), &mut warnings);
+
+            zone.ttl = base.ttl;
+            zone.reverse = base.reverse;
+            zone.keys = base.keys;
+            zone.soa = base.soa;
+            zone.nameservers = base.nameservers;
+            zone.subdomains = base.subdomains;
+        }
+
+        rtn.push(zone);
+    }
+
+    for warning in warnings {
+        warn!("{}", warning);
+    }
+
+    Ok(rtn)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resolve(templates: &str, zones: &str) -> error::Result<Vec<config::Zone>> {
+        resolve_zones(Some(serde_yaml::from_str(templates).unwrap()), serde_yaml::from_str(zones).unwrap())
+    }
+
+    fn subdomains(yaml: &str) -> Vec<config::SubDomain> {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    fn addresses(subdomain: &config::SubDomain) -> Vec<String> {
+        subdomain.records.iter()
+            .filter_map(|entry| match &entry.record {
+                config::Record::A {address: config::Ipv4Address::Single(config::Ipv4Type::Ip(ip)), ..} => Some(ip.to_string()),
+                _ => None
+            })
+            .collect()
+    }
+
+    #[test]
+    fn extends_order() {
+        let zones = resolve("
+base: {ttl: 100, keys: {a: base, b: base, c: base}}
+mail: {extends: [base], keys: {b: mail}}
+web: {extends: [base], ttl: 200, keys: {c: web}}
+", "
+- {name: example, extends: [mail, web], keys: {a: zone}}
+- {name: plain, ttl: 50}
+").unwrap();
+        let keys = zones[0].keys.as_ref().unwrap();
+
+        assert_eq!(zones[0].ttl, Some(200));
+        assert_eq!((keys["a"].as_str(), keys["b"].as_str(), keys["c"].as_str()), ("zone", "mail", "web"));
+        assert_eq!(zones[1].ttl, Some(50));
+        assert!(zones[1].keys.is_none());
+
+        let mut order = Vec::new();
+        let templates = serde_yaml::from_str("{base: {}, mail: {extends: [base]}, web: {extends: [base]}}").unwrap();
+        for name in ["mail", "web"] {
+            linearize(&name.to_owned(), &templates, &mut Vec::new(), &mut order).unwrap();
+        }
+        assert_eq!(order, vec!("base", "mail", "web"));
+    }
+
+    #[test]
+    fn template_loops() {
+        let loops = resolve(
+            "{a: {extends: [b]}, b: {extends: [c]}, c: {extends: [a]}}",
+            "[{name: example, extends: [a]}]"
+        );
+        assert!(loops.err().unwrap().get_msg().contains("zone template loop detected: a -> b -> c -> a"));
+
+        assert!(resolve("{}", "[{name: example, extends: [missing]}]").is_err());
+    }
+
+    #[test]
+    fn append_and_replace() {
+        let mut base = subdomains("
+- {name: www, records: [{type: A, address: 10.0.0.1}]}
+- {name: mail, records: [{type: A, address: 10.0.0.2}]}
+");
+        let mut warnings = Vec::new();
+
+        merge_subdomains(&mut base, subdomains("
+- {name: www, records: [{type: A, address: 10.0.0.3}]}
+- {name: mail, merge: Replace, records: [{type: A, address: 10.0.0.4}]}
+- {name: ftp, records: [{type: A, address: 10.0.0.5}]}
+"), "zone \"example\"", "template base", &mut warnings);
+
+        assert_eq!(base.iter().map(|s| s.name.as_str()).collect::<Vec<_>>(), vec!("www", "mail", "ftp"));
+        assert_eq!(addresses(&base[0]), vec!("10.0.0.1", "10.0.0.3"));
+        assert_eq!(addresses(&base[1]), vec!("10.0.0.4"));
+        assert_eq!(addresses(&base[2]), vec!("10.0.0.5"));
+        assert_eq!(warnings, vec!(
+            "subdomain \"www\" of zone \"example\" shadows 1 A record(s) from template base. both are kept, use the Replace merge mode to drop them",
+            "subdomain \"mail\" of zone \"example\" shadows 1 record(s) from template base"
+        ));
+    }
+
+    #[test]
+    fn repeated_records() {
+        let mut base = subdomains("[{name: www, records: [{type: A, address: 10.0.0.1}, {type: Txt, value: hi}]}]");
+        let mut warnings = Vec::new();
+
+        merge_subdomains(
+            &mut base,
+            subdomains("[{name: www, records: [{type: A, address: 10.0.0.1}, {type: A, address: 10.0.0.1, views: [lan]}]}]"),
+            "zone \"example\"",
+            "template base",
+            &mut warnings
+        );
+
+        assert_eq!(base[0].records.len(), 4);
+        assert_eq!(warnings, vec!("subdomain \"www\" of zone \"example\" repeats 1 record(s) from template base"));
+    }
+
+    #[test]
+    fn subdomain_views() {
+        let zones = resolve("
+internal:
+  subdomains:
+    - {name: db, views: [internal], records: [{type: A, address: 10.0.0.1}]}
+    - {name: www, views: [internal], records: [{type: A, address: 10.0.0.2}]}
+", "
+- name: example
+  extends: [internal]
+  subdomains:
+    - {name: db, records: [{type: A, address: 10.0.0.3}]}
+    - {name: www, views: [internal, external], records: [{type: A, address: 10.0.0.4}]}
+").unwrap();
+        let subdomains = &zones[0].subdomains;
+
+        assert_eq!(subdomains[0].views, Some(vec!("internal".to_owned())));
+        assert_eq!(addresses(&subdomains[0]), vec!("10.0.0.1", "10.0.0.3"));
+        assert_eq!(subdomains[1].views, Some(vec!("internal".to_owned(), "external".to_owned())));
+    }
+}
@@ -0,0 +1,319 @@
+use std::collections::{HashSet};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use crate::config;
+use crate::dns;
+use crate::error;
+use crate::net;
+use crate::template;
+
+/// max number of addresses a single fill is allowed to generate
+const FILL_LIMIT: u128 = 65536;
+
+/// generates PTR records, and optionally forward records, for every address
+/// of a reverse zone that did not receive a PTR record from the config
+pub struct ReverseFill {
+    zone: String,
+    prefix: net::IpAddrCidr,
+    start: u128,
+    finish: u128,
+    template: String,
+    forward: Option<String>,
+    ttl: usize,
+
+    /// keys of the reverse zone and the config, used after the address keys
+    keys: Vec<config::KeysMap>
+}
+
+impl ReverseFill {
+
+    pub fn new(
+        zone: String,
+        prefix: net::IpAddrCidr,
+        ttl: usize,
+        fill: config::ReverseFill,
+        keys: Vec<config::KeysMap>
+    ) -> error::Result<ReverseFill> {
+
+        let (start, finish) = if let Some(range) = &fill.range {
+            let parse = |value: &str| value.trim().parse::<IpAddr>().ok()
+                .filter(|ip| prefix.in_range(ip))
+                .map(|ip| net::ip_to_u128(&ip));
+
+            let parsed = match range.split_once('-') {
+                Some((s, f)) => parse(s).zip(parse(f)),
+                None => range.parse::<net::IpAddrCidr>().ok()
+                    .filter(|sub| prefix.contains(sub))
+                    .map(|sub| (net::ip_to_u128(&sub.start()), net::ip_to_u128(&sub.finish())))
+            };
+
+            match parsed {
+                Some((s, f)) if s <= f => (s, f),
+                _ => return Err(error::RuntimeError::ConfigError(format!(
+                    "invalid fill range \"{}\" for reverse zone {} ({})", range, zone, prefix
+                )))
+            }
+        } else {
+            match &prefix {
+                net::IpAddrCidr::V4(v4) => {
+                    let mut hosts = v4.hosts();
+                    let first = hosts.next().map(|ip| net::ipv4_to_u32(&ip) as u128).unwrap_or(0);
+                    let last = hosts.last().map(|ip| net::ipv4_to_u32(&ip) as u128).unwrap_or(first);
+
+                    (first, last)
+                },
+                net::IpAddrCidr::V6(_) => return Err(error::RuntimeError::ConfigError(format!(
+                    "fill for ipv6 reverse zone {} requires a range", zone
+                )))
+            }
+        };
+
+        if finish - start >= FILL_LIMIT {
+            return Err(error::RuntimeError::ConfigError(format!(
+                "fill for reverse zone {} covers more than {} addresses", zone, FILL_LIMIT
+            )));
+        }
+
+        if fill.forward.is_none() && !fill.template.ends_with('.') {
+            return Err(error::RuntimeError::ConfigError(format!(
+                "fill template for reverse zone {} must be absolute when no forward zone is given", zone
+            )));
+        }
+
+        Ok(ReverseFill {
+            zone,
+            prefix,
+            start,
+            finish,
+            template: fill.template,
+            forward: fill.forward,
+            ttl,
+            keys
+        })
+    }
+
+    fn address(&self, value: u128) -> IpAddr {
+        match self.prefix {
+            net::IpAddrCidr::V4(_) => IpAddr::V4(Ipv4Addr::from(value as u32)),
+            net::IpAddrCidr::V6(_) => IpAddr::V6(Ipv6Addr::from(value))
+        }
+    }
+
+    /// renders the template for an address. "{ip}" is the address with its
+    /// separators replaced by "-", "{a}" to "{d}" are the octets of an ipv4
+    /// address and "{hex}" is the full hex string of an ipv6 address. the
+    /// keys of the reverse zone and the config can be used as well
+    fn render(&self, ip: &IpAddr, forward_origin: Option<&String>) -> error::Result<String> {
+        let mut keys = config::KeysMap::new();
+        keys.insert("ip".to_owned(), ip.to_string().replace(['.', ':'], "-"));
+
+        match ip {
+            IpAddr::V4(v4) => {
+                for (key, octet) in ["a", "b", "c", "d"].iter().zip(v4.octets().iter()) {
+                    keys.insert((*key).to_owned(), octet.to_string());
+                }
+            },
+            IpAddr::V6(v6) => {
+                keys.insert("hex".to_owned(), format!("{:0>32x}", net::ipv6_to_u128(v6)));
+            }
+        }
+
+        let mut sources = vec!(&keys);
+        sources.extend(self.keys.iter());
+
+        let rtn = template::Scope::new(sources).render(&self.template)?;
+
+        Ok(match forward_origin {
+            Some(origin) if !rtn.ends_with('.') => format!("{}.{}", rtn, origin),
+            _ => rtn
+        })
+    }
+
+    fn add_records(storage: &mut dns::ZoneStorage, zone: &String, comment: &str, records: Vec<dns::record::Record>) {
+        if records.is_empty() {
+            return;
+        }
+
+        if storage.get_zones_ref().get(zone).is_some_and(|z| !z.get_records_ref().is_empty()) {
+            storage.add_record_to(zone, dns::record::Record::Blank);
+        }
+
+        storage.add_record_to(zone, dns::record::Record::Comment(comment.to_owned()));
+
+        for record in records {
+            storage.add_record_to(zone, record);
+        }
+    }
+
+    /// adds the generated records to the storage. addresses that already
+    /// have a PTR record in the reverse zone are skipped. ipv4 fills without
+    /// a range skip the network and broadcast addresses
+    pub fn apply(&self, storage: &mut dns::ZoneStorage) -> error::Result<()> {
+        let forward_origin = match &self.forward {
+            Some(forward) => Some(storage.get_zones_ref().get(forward).ok_or_else(|| error::RuntimeError::ConfigError(
+                format!("unknown forward zone \"{}\" for fill of reverse zone {}", forward, self.zone)
+            ))?.get_origin_ref().clone()),
+            None => None
+        };
+
+        let populated: HashSet<IpAddr> = storage.get_zones_ref().get(&self.zone)
+            .map(|zone| zone.get_records_ref().iter().filter_map(|record| match record {
+                dns::record::Record::Ptr {name, ..} => dns::reverse_name_to_ip(name),
+                _ => None
+            }).collect())
+            .unwrap_or_default();
+
+        let mut ptr_records: Vec<dns::record::Record> = Vec::new();
+        let mut forward_records: Vec<dns::record::Record> = Vec::new();
+
+        for value in self.start..=self.finish {
+            let ip = self.address(value);
+
+            if populated.contains(&ip) {
+                continue;
+            }
+
+            let domain = self.render(&ip, forward_origin.as_ref())?;
+
+            if let (Some(forward), Some(origin)) = (&self.forward, &forward_origin) {
+                if !dns::in_bailiwick(&dns::normalize_domain(&domain), &dns::normalize_domain(origin)) {
+                    return Err(error::RuntimeError::ConfigError(format!(
+                        "fill of reverse zone {} generates {} outside of forward zone \"{}\"", self.zone, domain, forward
+                    )));
+                }
+            }
+
+            ptr_records.push(dns::record::Record::Ptr {
+                name: dns::ip_reverse_string(&ip, true)?,
+                ttl: self.ttl,
+                domain: domain.clone()
+            });
+
+            if forward_origin.is_some() {
+                forward_records.push(match ip {
+                    IpAddr::V4(address) => dns::record::Record::A {name: domain, ttl: self.ttl, address},
+                    IpAddr::V6(address) => dns::record::Record::Aaaa {name: domain, ttl: self.ttl, address}
+                });
+            }
+        }
+
+        let comment = format!("generated fill for {}", self.prefix);
+
+        ReverseFill::add_records(storage, &self.zone, &comment, ptr_records);
+
+        if let Some(forward) = &self.forward {
+            ReverseFill::add_records(storage, forward, &comment, forward_records);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reverse_fill(prefix: &str, fill: &str) -> error::Result<ReverseFill> {
+        ReverseFill::new("rev".to_owned(), prefix.parse().unwrap(), 300, serde_yaml::from_str(fill).unwrap(), vec!())
+    }
+
+    fn zones(prefix: &str) -> dns::ZoneStorage {
+        let mut storage = dns::ZoneStorage::new();
+        storage.add_zone(dns::zone::Zone::new("example".to_owned(), "example.".to_owned()));
+
+        match prefix.parse().unwrap() {
+            net::IpAddrCidr::V4(v4) => storage.add_v4_rev_zone(
+                dns::zone::Zone::new("rev".to_owned(), dns::ipv4_reverse_prefix(&v4, true).unwrap()), v4
+            ),
+            net::IpAddrCidr::V6(v6) => storage.add_v6_rev_zone(
+                dns::zone::Zone::new("rev".to_owned(), dns::ipv6_reverse_prefix(&v6, true).unwrap()), v6
+            )
+        };
+
+        storage
+    }
+
+    fn ptr_domains(storage: &dns::ZoneStorage) -> Vec<(String, String)> {
+        storage.get_zones_ref()["rev"].get_records_ref().iter()
+            .filter_map(|record| match record {
+                dns::record::Record::Ptr {name, domain, ..} => Some((name.clone(), domain.clone())),
+                _ => None
+            })
+            .collect()
+    }
+
+    #[test]
+    fn skips_populated_addresses() {
+        let mut storage = zones("10.0.0.0/24");
+        // the PTR an A record with reverse set adds to the reverse zone
+        storage.add_v4_reverse_record(&Ipv4Addr::new(10, 0, 0, 5), dns::record::Record::Ptr {
+            name: "5.0.0.10.in-addr.arpa.".to_owned(),
+            ttl: 300,
+            domain: "mail.example.".to_owned()
+        });
+
+        reverse_fill("10.0.0.0/24", "{template: \"host-{d}\", forward: example}").unwrap()
+            .apply(&mut storage)
+            .unwrap();
+
+        let ptrs = ptr_domains(&storage);
+        assert_eq!(ptrs.len(), 254);
+        assert_eq!(ptrs.iter().filter(|(name, _)| name == "5.0.0.10.in-addr.arpa.").count(), 1);
+        assert!(ptrs.contains(&("1.0.0.10.in-addr.arpa.".to_owned(), "host-1.example.".to_owned())));
+        assert!(ptrs.contains(&("254.0.0.10.in-addr.arpa.".to_owned(), "host-254.example.".to_owned())));
+
+        let forward: Vec<&String> = storage.get_zones_ref()["example"].get_records_ref().iter()
+            .filter_map(|record| match record {
+                dns::record::Record::A {name, ..} => Some(name),
+                _ => None
+            })
+            .collect();
+        assert_eq!(forward.len(), 253);
+        assert!(!forward.iter().any(|name| *name == "host-5.example."));
+    }
+
+    #[test]
+    fn address_keys() {
+        let mut storage = zones("10.1.2.0/24");
+        reverse_fill("10.1.2.0/24", "{template: \"{d}.{c}.{b}.{a}.{ip}.example.\", range: 10.1.2.7-10.1.2.7}").unwrap()
+            .apply(&mut storage)
+            .unwrap();
+
+        assert_eq!(ptr_domains(&storage), vec!(
+            ("7.2.1.10.in-addr.arpa.".to_owned(), "7.2.1.10.10-1-2-7.example.".to_owned())
+        ));
+
+        let mut v6_storage = zones("2001:db8::/64");
+        reverse_fill("2001:db8::/64", "{template: \"{hex}.{ip}.example.\", range: \"2001:db8::a - 2001:db8::a\"}").unwrap()
+            .apply(&mut v6_storage)
+            .unwrap();
+
+        assert_eq!(ptr_domains(&v6_storage)[0].1, "20010db800000000000000000000000a.2001-db8--a.example.");
+    }
+
+    #[test]
+    fn invalid_fills() {
+        assert!(reverse_fill("2001:db8::/64", "{template: \"{hex}.example.\"}").is_err());
+        assert!(reverse_fill("10.0.0.0/24", "{template: \"{d}\"}").is_err());
+        assert!(reverse_fill("10.0.0.0/24", "{template: \"{d}.example.\", range: 10.0.1.0/28}").is_err());
+        assert!(reverse_fill("10.0.0.0/24", "{template: \"{d}.example.\", range: 10.0.0.9-10.0.0.2}").is_err());
+    }
+
+    #[test]
+    fn fill_limit() {
+        assert!(reverse_fill("10.0.0.0/16", "{template: \"{c}-{d}.example.\"}").is_ok());
+        assert!(reverse_fill("10.0.0.0/15", "{template: \"{c}-{d}.example.\"}").err().unwrap()
+            .get_msg().contains("covers more than 65536 addresses"));
+        assert!(reverse_fill("2001:db8::/64", "{template: \"{hex}.example.\", range: \"2001:db8::/112\"}").is_ok());
+        assert!(reverse_fill("2001:db8::/64", "{template: \"{hex}.example.\", range: \"2001:db8::/111\"}").is_err());
+    }
+
+    #[test]
+    fn out_of_bailiwick() {
+        let mut storage = zones("10.0.0.0/24");
+        let result = reverse_fill("10.0.0.0/24", "{template: \"host-{d}.other.test.\", forward: example}").unwrap()
+            .apply(&mut storage);
+
+        assert!(result.unwrap_err().get_msg().contains("outside of forward zone \"example\""));
+    }
+}
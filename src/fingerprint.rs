@@ -0,0 +1,488 @@
+use std::fmt::{Write};
+
+use base64::{Engine};
+use base64::engine::general_purpose::{STANDARD as BASE64};
+use sha1::{Sha1};
+use sha2::{Digest, Sha256, Sha512};
+
+use crate::config;
+use crate::context;
+use crate::error;
+
+fn to_hex(bytes: &[u8]) -> String {
+    let mut rtn = String::with_capacity(bytes.len() * 2);
+
+    for byte in bytes {
+        write!(&mut rtn, "{:0>2x}", byte).unwrap();
+    }
+
+    rtn
+}
+
+/// validates a hex string given in the config and returns it in lowercase.
+/// whitespace and colons are allowed as separators
+fn check_hex(given: &str, expected_len: Option<usize>) -> error::Result<String> {
+    let hex: String = given.chars()
+        .filter(|c| !c.is_whitespace() && *c != ':')
+        .map(|c| c.to_ascii_lowercase())
+        .collect();
+
+    if hex.is_empty() || !hex.len().is_multiple_of(2) || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(error::RuntimeError::ConfigError(format!("invalid hex data given: \"{}\"", given)));
+    }
+
+    if let Some(len) = expected_len {
+        if hex.len() != len * 2 {
+            return Err(error::RuntimeError::ConfigError(format!(
+                "hex data has wrong length. expected {} bytes given: {}", len, hex.len() / 2
+            )));
+        }
+    }
+
+    Ok(hex)
+}
+
+/// decodes the first pem block with the given label
+fn pem_decode(contents: &str, label: &str) -> Option<Vec<u8>> {
+    let begin = format!("-----BEGIN {}-----", label);
+    let end = format!("-----END {}-----", label);
+    let mut encoded = String::new();
+    let mut in_block = false;
+
+    for line in contents.lines() {
+        let line = line.trim();
+
+        if line == begin {
+            in_block = true;
+        } else if line == end {
+            if in_block {
+                return BASE64.decode(encoded.as_bytes()).ok();
+            }
+        } else if in_block {
+            encoded.push_str(line);
+        }
+    }
+
+    None
+}
+
+/// tag, full element, contents and the remaining data of a der element
+type DerElement<'a> = (u8, &'a [u8], &'a [u8], &'a [u8]);
+
+/// reads a single der element returning its tag, the full element, the
+/// contents and everything following it
+fn der_element(data: &[u8]) -> Option<DerElement<'_>> {
+    let tag = *data.first()?;
+    let first = *data.get(1)? as usize;
+    let (header_len, content_len) = if first < 0x80 {
+        (2, first)
+    } else {
+        let count = first & 0x7f;
+
+        if count == 0 || count > 4 {
+            return None;
+        }
+
+        let mut len = 0usize;
+
+        for byte in data.get(2..2 + count)? {
+            len = (len << 8) | *byte as usize;
+        }
+
+        (2 + count, len)
+    };
+
+    let total = header_len.checked_add(content_len)?;
+
+    if data.len() < total {
+        return None;
+    }
+
+    Some((tag, &data[..total], &data[header_len..total], &data[total..]))
+}
+
+/// pulls the SubjectPublicKeyInfo out of a der encoded x509 certificate
+fn certificate_spki(cert: &[u8]) -> Option<&[u8]> {
+    let (_, _, certificate, _) = der_element(cert)?;
+    let (_, _, mut tbs, _) = der_element(certificate)?;
+
+    // optional explicit version tag
+    if tbs.first() == Some(&0xa0) {
+        tbs = der_element(tbs)?.3;
+    }
+
+    // serial number, signature algorithm, issuer, validity and subject
+    for _ in 0..5 {
+        tbs = der_element(tbs)?.3;
+    }
+
+    let (tag, spki, _, _) = der_element(tbs)?;
+
+    if tag == 0x30 {
+        Some(spki)
+    } else {
+        None
+    }
+}
+
+pub fn tlsa_usage(usage: &config::TlsaUsage) -> u8 {
+    match usage {
+        config::TlsaUsage::PkixTa => 0,
+        config::TlsaUsage::PkixEe => 1,
+        config::TlsaUsage::DaneTa => 2,
+        config::TlsaUsage::DaneEe => 3
+    }
+}
+
+pub fn tlsa_selector(selector: &config::TlsaSelector) -> u8 {
+    match selector {
+        config::TlsaSelector::Cert => 0,
+        config::TlsaSelector::Spki => 1
+    }
+}
+
+pub fn tlsa_matching(matching: &config::TlsaMatching) -> u8 {
+    match matching {
+        config::TlsaMatching::Full => 0,
+        config::TlsaMatching::Sha256 => 1,
+        config::TlsaMatching::Sha512 => 2
+    }
+}
+
+/// returns the owner name of a tlsa record for the given port and protocol
+pub fn tlsa_domain(port: u16, protocol: &Option<config::TlsaProtocol>, domain: &str) -> String {
+    let protocol = match protocol {
+        Some(config::TlsaProtocol::Udp) => "udp",
+        Some(config::TlsaProtocol::Sctp) => "sctp",
+        Some(config::TlsaProtocol::Tcp) | None => "tcp"
+    };
+
+    format!("_{}._{}.{}", port, protocol, domain)
+}
+
+/// returns the certificate association data of a tlsa record. the data is
+/// either given as hex or computed from the first certificate of a pem file
+pub fn tlsa_data(
+    config: &context::ConfigContext,
+    detail: &config::TlsaDetail
+) -> error::Result<String> {
+    let matching = detail.matching.as_ref().unwrap_or(&config::TlsaMatching::Sha256);
+
+    match (&detail.data, &detail.cert_file) {
+        (Some(data), None) => check_hex(data, match matching {
+            config::TlsaMatching::Full => None,
+            config::TlsaMatching::Sha256 => Some(32),
+            config::TlsaMatching::Sha512 => Some(64)
+        }),
+        (None, Some(cert_file)) => {
+            let path = config.resolve_path(cert_file);
+            let contents = std::fs::read_to_string(&path)?;
+            let cert = pem_decode(&contents, "CERTIFICATE").ok_or_else(|| error::RuntimeError::ConfigError(
+                format!("failed to find a pem certificate in {}", path.display())
+            ))?;
+            let selected = match detail.selector.as_ref().unwrap_or(&config::TlsaSelector::Spki) {
+                config::TlsaSelector::Cert => cert.as_slice(),
+                config::TlsaSelector::Spki => certificate_spki(&cert).ok_or_else(|| error::RuntimeError::ConfigError(
+                    format!("failed to parse certificate in {}", path.display())
+                ))?
+            };
+
+            Ok(match matching {
+                config::TlsaMatching::Full => to_hex(selected),
+                config::TlsaMatching::Sha256 => to_hex(&Sha256::digest(selected)),
+                config::TlsaMatching::Sha512 => to_hex(&Sha512::digest(selected))
+            })
+        },
+        _ => Err(error::RuntimeError::ConfigError(format!(
+            "tlsa record for port {} requires exactly one of data or cert_file", detail.port
+        )))
+    }
+}
+
+fn sshfp_algorithm_value(algorithm: &config::SshfpAlgorithm) -> u8 {
+    match algorithm {
+        config::SshfpAlgorithm::Rsa => 1,
+        config::SshfpAlgorithm::Dsa => 2,
+        config::SshfpAlgorithm::Ecdsa => 3,
+        config::SshfpAlgorithm::Ed25519 => 4,
+        config::SshfpAlgorithm::Ed448 => 6
+    }
+}
+
+fn sshfp_key_algorithm(key_type: &str) -> Option<config::SshfpAlgorithm> {
+    match key_type {
+        "ssh-rsa" => Some(config::SshfpAlgorithm::Rsa),
+        "ssh-dss" => Some(config::SshfpAlgorithm::Dsa),
+        "ssh-ed25519" => Some(config::SshfpAlgorithm::Ed25519),
+        "ssh-ed448" => Some(config::SshfpAlgorithm::Ed448),
+        _ if key_type.starts_with("ecdsa-sha2-") => Some(config::SshfpAlgorithm::Ecdsa),
+        _ => None
+    }
+}
+
+/// returns the algorithm, fingerprint type and fingerprint of an sshfp
+/// record. the fingerprint is either given as hex or computed from an
+/// openssh public key file
+pub fn sshfp_data(
+    config: &context::ConfigContext,
+    detail: &config::SshfpDetail
+) -> error::Result<(u8, u8, String)> {
+    let fingerprint_type = detail.fingerprint_type.as_ref().unwrap_or(&config::SshfpFingerprint::Sha256);
+    let type_value = match fingerprint_type {
+        config::SshfpFingerprint::Sha1 => 1,
+        config::SshfpFingerprint::Sha256 => 2
+    };
+
+    match (&detail.fingerprint, &detail.key_file) {
+        (Some(fingerprint), None) => {
+            let algorithm = detail.algorithm.as_ref().ok_or_else(|| error::RuntimeError::ConfigError(
+                "sshfp record with a fingerprint requires an algorithm".to_owned()
+            ))?;
+
+            Ok((
+                sshfp_algorithm_value(algorithm),
+                type_value,
+                check_hex(fingerprint, Some(if type_value == 1 { 20 } else { 32 }))?
+            ))
+        },
+        (None, Some(key_file)) => {
+            let path = config.resolve_path(key_file);
+            let contents = std::fs::read_to_string(&path)?;
+            let line = contents.lines()
+                .map(|l| l.trim())
+                .find(|l| !l.is_empty() && !l.starts_with('#'))
+                .ok_or_else(|| error::RuntimeError::ConfigError(format!(
+                    "no public key found in {}", path.display()
+                )))?;
+            let mut fields = line.split_whitespace();
+            let key_type = fields.next().unwrap_or_default();
+            let blob = fields.next()
+                .and_then(|b| BASE64.decode(b.as_bytes()).ok())
+                .ok_or_else(|| error::RuntimeError::ConfigError(format!(
+                    "invalid openssh public key in {}", path.display()
+                )))?;
+
+            // the blob starts with the length prefixed key type
+            let blob_type = blob.get(0..4)
+                .map(|len| u32::from_be_bytes([len[0], len[1], len[2], len[3]]) as usize)
+                .and_then(|len| blob.get(4..4 + len));
+
+            if blob_type != Some(key_type.as_bytes()) {
+                return Err(error::RuntimeError::ConfigError(format!(
+                    "invalid openssh public key in {}", path.display()
+                )));
+            }
+
+            let algorithm = sshfp_key_algorithm(key_type).ok_or_else(|| error::RuntimeError::ConfigError(
+                format!("unsupported ssh key type \"{}\" in {}", key_type, path.display())
+            ))?;
+
+            if let Some(given) = &detail.algorithm {
+                if sshfp_algorithm_value(given) != sshfp_algorithm_value(&algorithm) {
+                    return Err(error::RuntimeError::ConfigError(format!(
+                        "sshfp algorithm {:?} does not match key type \"{}\" in {}", given, key_type, path.display()
+                    )));
+                }
+            }
+
+            Ok((
+                sshfp_algorithm_value(&algorithm),
+                type_value,
+                match fingerprint_type {
+                    config::SshfpFingerprint::Sha1 => to_hex(&Sha1::digest(&blob)),
+                    config::SshfpFingerprint::Sha256 => to_hex(&Sha256::digest(&blob))
+                }
+            ))
+        },
+        _ => Err(error::RuntimeError::ConfigError(
+            "sshfp record requires exactly one of fingerprint or key_file".to_owned()
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // self signed prime256v1 certificate for www.example.com
+    const CERTIFICATE: &str = concat!(
+        "-----BEGIN CERTIFICATE-----\n",
+        "MIIBjDCCATGgAwIBAgIUA2Detb+05Jft7a2WgnZHxODLrrQwCgYIKoZIzj0EAwIw\n",
+        "GjEYMBYGA1UEAwwPd3d3LmV4YW1wbGUuY29tMCAXDTI2MTAxODIwNTk1OVoYDzIx\n",
+        "MjYwOTI0MjA1OTU5WjAaMRgwFgYDVQQDDA93d3cuZXhhbXBsZS5jb20wWTATBgcq\n",
+        "hkjOPQIBBggqhkjOPQMBBwNCAAQgraNbEvnspqhjVCxqQgy8h4wkazRYeBDo7jxb\n",
+        "+i0syfVHDwIGmL2tKjvDwDYSOhUBIep2JzaKwYde+oMCnc7To1MwUTAdBgNVHQ4E\n",
+        "FgQUtAk2hpG/XN5tgOlMIpMY43decPwwHwYDVR0jBBgwFoAUtAk2hpG/XN5tgOlM\n",
+        "IpMY43decPwwDwYDVR0TAQH/BAUwAwEB/zAKBggqhkjOPQQDAgNJADBGAiEA/shH\n",
+        "mF39ZUSUZ09tMgX1rBAsFbg+/duaRjVQBJIYUvYCIQD1qpL5fNdfBnMlQ4L7bSzi\n",
+        "K6faL1RGSrvbyVMMjBp9ZA==\n",
+        "-----END CERTIFICATE-----\n",
+    );
+
+    const PUBLIC_KEY: &str = "ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIHnGEzilGCdlsCEo9P2Phg4Rs/joCwkBvtqOFKdnJznz host@example\n";
+
+    fn config(name: &str) -> context::ConfigContext {
+        let directory = std::env::temp_dir().join(format!("dns-zones-builder-fingerprint-{}-{}", std::process::id(), name));
+        let _ = std::fs::remove_dir_all(&directory);
+        std::fs::create_dir_all(&directory).unwrap();
+        std::fs::write(directory.join("cert.pem"), CERTIFICATE).unwrap();
+        std::fs::write(directory.join("host.pub"), format!("# host key\n{}", PUBLIC_KEY)).unwrap();
+
+        let config = serde_yaml::from_str::<config::Config>("name: test\nzones: []").unwrap();
+
+        context::ConfigContext::new(config, &directory.join("config.yaml"))
+    }
+
+    fn tlsa(selector: config::TlsaSelector, matching: config::TlsaMatching) -> config::TlsaDetail {
+        config::TlsaDetail {
+            port: 443,
+            protocol: None,
+            usage: None,
+            selector: Some(selector),
+            matching: Some(matching),
+            data: None,
+            cert_file: Some("cert.pem".to_owned())
+        }
+    }
+
+    fn sshfp(algorithm: Option<config::SshfpAlgorithm>, fingerprint_type: config::SshfpFingerprint) -> config::SshfpDetail {
+        config::SshfpDetail {
+            algorithm,
+            fingerprint_type: Some(fingerprint_type),
+            fingerprint: None,
+            key_file: Some("host.pub".to_owned())
+        }
+    }
+
+    fn der(tag: u8, contents: &[u8]) -> Vec<u8> {
+        let mut rtn = vec!(tag);
+
+        if contents.len() < 0x80 {
+            rtn.push(contents.len() as u8);
+        } else {
+            rtn.extend_from_slice(&[0x82, (contents.len() >> 8) as u8, contents.len() as u8]);
+        }
+
+        rtn.extend_from_slice(contents);
+        rtn
+    }
+
+    fn certificate(version: bool, spki: &[u8]) -> Vec<u8> {
+        let mut tbs = vec!();
+
+        if version {
+            tbs.extend(der(0xa0, &der(0x02, &[0x02])));
+        }
+
+        tbs.extend(der(0x02, &[0x01, 0x02]));
+        tbs.extend(der(0x30, &der(0x06, &[0x2a, 0x86, 0x48])));
+        tbs.extend(der(0x30, b"issuer"));
+        tbs.extend(der(0x30, &[0; 200]));
+        tbs.extend(der(0x30, b"subject"));
+        tbs.extend_from_slice(spki);
+
+        let mut cert = der(0x30, &tbs);
+        cert.extend(der(0x30, &der(0x06, &[0x2a, 0x86, 0x48])));
+        cert.extend(der(0x03, &[0x00, 0xff]));
+
+        der(0x30, &cert)
+    }
+
+    #[test]
+    fn der_elements() {
+        let short = der(0x04, b"abc");
+        let (tag, element, contents, rest) = der_element(&short).unwrap();
+        assert_eq!((tag, element, contents, rest), (0x04, short.as_slice(), &b"abc"[..], &[][..]));
+
+        let mut long = der(0x04, &[7; 300]);
+        long.push(0x05);
+        let (_, element, contents, rest) = der_element(&long).unwrap();
+        assert_eq!((element.len(), contents.len(), rest), (304, 300, &[0x05][..]));
+
+        assert!(der_element(&long[..100]).is_none());
+        assert!(der_element(&[0x04]).is_none());
+        assert!(der_element(&[0x04, 0x80]).is_none());
+        assert!(der_element(&[0x04, 0x85, 0, 0, 0, 0, 1]).is_none());
+    }
+
+    #[test]
+    fn spki_of_certificate() {
+        let spki = der(0x30, &der(0x03, &[0x00, 1, 2, 3]));
+
+        assert_eq!(certificate_spki(&certificate(true, &spki)), Some(spki.as_slice()));
+        assert_eq!(certificate_spki(&certificate(false, &spki)), Some(spki.as_slice()));
+        assert_eq!(certificate_spki(&certificate(true, &der(0x04, b"key"))), None);
+        assert_eq!(certificate_spki(&certificate(true, &[])), None);
+    }
+
+    #[test]
+    fn pem() {
+        let cert = certificate(true, &der(0x30, b"key"));
+        let contents = format!(
+            "subject=CN = example\n-----BEGIN CERTIFICATE-----\n{}\n{}\n-----END CERTIFICATE-----\n",
+            &BASE64.encode(&cert)[..64], &BASE64.encode(&cert)[64..]
+        );
+
+        assert_eq!(pem_decode(&contents, "CERTIFICATE"), Some(cert));
+        assert_eq!(pem_decode(&contents, "PUBLIC KEY"), None);
+        assert_eq!(pem_decode("-----BEGIN CERTIFICATE-----\nAAAA\n", "CERTIFICATE"), None);
+    }
+
+    #[test]
+    fn hex() {
+        assert_eq!(check_hex("AB:cd 01", Some(3)).unwrap(), "abcd01");
+        assert!(check_hex("abc", None).is_err());
+        assert!(check_hex("zz", None).is_err());
+        assert!(check_hex("abcd", Some(32)).is_err());
+        assert_eq!(to_hex(&[0x00, 0x0f, 0xff]), "000fff");
+    }
+
+    // expected digests from
+    // openssl x509 -in cert.pem -pubkey -noout | openssl pkey -pubin -outform DER | sha256sum
+    // openssl x509 -in cert.pem -outform DER | sha256sum
+    #[test]
+    fn tlsa_certificate() {
+        let config = config("tlsa");
+
+        assert_eq!(
+            tlsa_data(&config, &tlsa(config::TlsaSelector::Spki, config::TlsaMatching::Sha256)).unwrap(),
+            "33845ca37862bfdac4f7f3c6a7d97b95d339e5eea996a916bad610c91e5d5a3e"
+        );
+        assert_eq!(
+            tlsa_data(&config, &tlsa(config::TlsaSelector::Spki, config::TlsaMatching::Sha512)).unwrap(),
+            concat!(
+                "c0c942bfa1ea847af531ec30ca97319447a9c5ff548b3621f83568741b4d938f",
+                "e66185057083eda80d41055739f7a5157ecdeaf424049e7c768ecd112b7fecae"
+            )
+        );
+        assert_eq!(
+            tlsa_data(&config, &tlsa(config::TlsaSelector::Cert, config::TlsaMatching::Sha256)).unwrap(),
+            "8656a84c12eefebdd3b8e97ef97fbc49ba5d4c86af734bb5578ef52fef957b0f"
+        );
+
+        let full = tlsa_data(&config, &tlsa(config::TlsaSelector::Cert, config::TlsaMatching::Full)).unwrap();
+        let der: String = CERTIFICATE.lines().filter(|l| !l.starts_with("-----")).collect();
+        assert_eq!(full, to_hex(&BASE64.decode(der).unwrap()));
+
+        let both = config::TlsaDetail { data: Some("00".to_owned()), ..tlsa(config::TlsaSelector::Spki, config::TlsaMatching::Full) };
+        assert!(tlsa_data(&config, &both).is_err());
+    }
+
+    // expected fingerprints from ssh-keygen -r www.example.com -f host.pub
+    #[test]
+    fn sshfp_public_key() {
+        let config = config("sshfp");
+
+        assert_eq!(
+            sshfp_data(&config, &sshfp(None, config::SshfpFingerprint::Sha1)).unwrap(),
+            (4, 1, "5dcc110c5503464433013bb35c40aa6f143e33fb".to_owned())
+        );
+        assert_eq!(
+            sshfp_data(&config, &sshfp(Some(config::SshfpAlgorithm::Ed25519), config::SshfpFingerprint::Sha256)).unwrap(),
+            (4, 2, "1c9511caf705b4f588d1a462e841732bdcf149ecb5874c236df569c74ccec887".to_owned())
+        );
+
+        let mismatch = sshfp_data(&config, &sshfp(Some(config::SshfpAlgorithm::Rsa), config::SshfpFingerprint::Sha256));
+        assert!(mismatch.err().unwrap().get_msg().contains("does not match key type \"ssh-ed25519\""));
+    }
+}
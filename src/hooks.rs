@@ -0,0 +1,174 @@
+use std::path::{Path};
+
+use log::{error, info};
+
+use crate::config;
+use crate::error;
+use crate::report;
+use crate::template;
+
+/// escapes braces so that a value is not rendered again
+fn literal(value: &str) -> String {
+    value.replace('{', "{{").replace('}', "}}")
+}
+
+/// splits a hook command into arguments the way a shell would, without
+/// expanding anything. whitespace separates arguments, single quotes keep
+/// everything up to the closing quote, double quotes do the same but allow
+/// \" and \\ inside and a backslash outside of quotes keeps the next
+/// character
+fn split_command(command: &str) -> error::Result<Vec<String>> {
+    let unclosed = || error::RuntimeError::ConfigError(format!(
+        "unclosed quote in hook command \"{}\"", command
+    ));
+    let mut rtn = Vec::new();
+    let mut arg: Option<String> = None;
+    let mut chars = command.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' => {
+                let arg = arg.get_or_insert_with(String::new);
+
+                loop {
+                    match chars.next().ok_or_else(unclosed)? {
+                        '\'' => break,
+                        c => arg.push(c)
+                    }
+                }
+            },
+            '"' => {
+                let arg = arg.get_or_insert_with(String::new);
+
+                loop {
+                    match chars.next().ok_or_else(unclosed)? {
+                        '"' => break,
+                        '\\' => match chars.next().ok_or_else(unclosed)? {
+                            c @ ('"' | '\\') => arg.push(c),
+                            c => {
+                                arg.push('\\');
+                                arg.push(c);
+                            }
+                        },
+                        c => arg.push(c)
+                    }
+                }
+            },
+            '\\' => {
+                let c = chars.next().ok_or_else(|| error::RuntimeError::ConfigError(format!(
+                    "hook command \"{}\" ends with a backslash", command
+                )))?;
+
+                arg.get_or_insert_with(String::new).push(c);
+            },
+            c if c.is_whitespace() => {
+                if let Some(arg) = arg.take() {
+                    rtn.push(arg);
+                }
+            },
+            c => arg.get_or_insert_with(String::new).push(c)
+        }
+    }
+
+    rtn.extend(arg);
+
+    Ok(rtn)
+}
+
+/// renders the arguments of a hook command. the command is split into
+/// arguments before rendering and is not run through a shell so values
+/// containing spaces stay a single argument
+fn render_command(command: &str, keys: &config::KeysMap) -> error::Result<Vec<String>> {
+    let scope = template::Scope::new(vec!(keys));
+    let mut rtn = Vec::new();
+
+    for arg in split_command(command)? {
+        rtn.push(scope.render(&arg)?);
+    }
+
+    if rtn.is_empty() {
+        return Err(error::RuntimeError::ConfigError("hook command is empty".to_owned()));
+    }
+
+    Ok(rtn)
+}
+
+/// runs the hooks of a zone in order and logs their output. stops at the
+/// first hook that does not exit successfully. returns the result of every
+/// hook that ran
+pub fn run(
+    hooks: &[config::Hook],
+    zone: &str,
+    origin: &str,
+    path: &Path
+) -> error::Result<Vec<report::HookReport>> {
+    let mut keys = config::KeysMap::new();
+    keys.insert("zone".to_owned(), literal(zone));
+    keys.insert("origin".to_owned(), literal(origin));
+    keys.insert("path".to_owned(), literal(&path.to_string_lossy()));
+
+    let mut rtn = Vec::with_capacity(hooks.len());
+
+    for hook in hooks {
+        let args = render_command(&hook.command, &keys)?;
+        let command = args.join(" ");
+
+        info!("running hook for zone {}: {}", zone, command);
+
+        let (success, output) = match std::process::Command::new(&args[0]).args(&args[1..]).output() {
+            Ok(cmd) => {
+                let mut output = String::from_utf8_lossy(&cmd.stdout).into_owned();
+                output.push_str(&String::from_utf8_lossy(&cmd.stderr));
+
+                for line in output.lines() {
+                    info!("{}", line);
+                }
+
+                if !cmd.status.success() {
+                    error!("hook for zone {} failed. {}", zone, cmd.status);
+                }
+
+                (cmd.status.success(), output)
+            },
+            Err(err) => {
+                error!("failed to run hook for zone {}. {}", zone, err);
+
+                (false, err.to_string())
+            }
+        };
+
+        rtn.push(report::HookReport { command, success, output });
+
+        if !success {
+            break;
+        }
+    }
+
+    Ok(rtn)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn whitespace() {
+        assert_eq!(split_command("  rndc  reload\t{zone} ").unwrap(), vec!("rndc", "reload", "{zone}"));
+        assert!(split_command("   ").unwrap().is_empty());
+    }
+
+    #[test]
+    fn quotes() {
+        assert_eq!(split_command("sh -c 'exit 3'").unwrap(), vec!("sh", "-c", "exit 3"));
+        assert_eq!(split_command("echo \"a \\\"b\\\" \\n\"").unwrap(), vec!("echo", "a \"b\" \\n"));
+        assert_eq!(split_command("echo '' a'b c'd").unwrap(), vec!("echo", "", "ab cd"));
+        assert_eq!(split_command("echo a\\ b \\'").unwrap(), vec!("echo", "a b", "'"));
+    }
+
+    #[test]
+    fn unclosed() {
+        assert!(split_command("sh -c 'exit 3").is_err());
+        assert!(split_command("echo \"a").is_err());
+        assert!(split_command("echo a\\").is_err());
+    }
+}
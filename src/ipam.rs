@@ -0,0 +1,440 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::net::{IpAddr};
+use std::path::{PathBuf};
+
+use crate::config;
+use crate::context;
+use crate::deploy;
+use crate::dns;
+use crate::error;
+use crate::net;
+
+/// pool name -> owner -> allocated address
+type LockMap = BTreeMap<String, BTreeMap<String, IpAddr>>;
+
+struct Pool {
+    name: String,
+    prefix: net::IpAddrCidr,
+    reserved: Vec<(u128, u128)>
+}
+
+impl Pool {
+
+    fn new(name: String, pool: config::Pool) -> error::Result<Pool> {
+        let mut reserved = Vec::new();
+
+        for given in pool.reserved.unwrap_or_default() {
+            let parse = |value: &str| value.trim().parse::<IpAddr>().ok();
+            let range = if let Some((start, finish)) = given.split_once('-') {
+                parse(start).zip(parse(finish))
+            } else if given.contains('/') {
+                given.parse::<net::IpAddrCidr>().ok().map(|prefix| (prefix.start(), prefix.finish()))
+            } else {
+                parse(&given).map(|ip| (ip, ip))
+            };
+
+            match range {
+                Some((start, finish)) if pool.prefix.in_range(&start) && pool.prefix.in_range(&finish) && start <= finish => {
+                    reserved.push((net::ip_to_u128(&start), net::ip_to_u128(&finish)));
+                },
+                _ => return Err(error::RuntimeError::ConfigError(format!(
+                    "invalid reserved range \"{}\" for pool \"{}\" ({})", given, name, pool.prefix
+                )))
+            }
+        }
+
+        Ok(Pool {
+            name,
+            prefix: pool.prefix,
+            reserved
+        })
+    }
+
+    fn is_v4(&self) -> bool {
+        matches!(self.prefix, net::IpAddrCidr::V4(_))
+    }
+
+    fn is_reserved(&self, ip: &IpAddr) -> bool {
+        let value = net::ip_to_u128(ip);
+
+        self.reserved.iter().any(|(start, finish)| value >= *start && value <= *finish)
+    }
+
+    fn is_available(&self, ip: &IpAddr, used: &HashSet<IpAddr>) -> bool {
+        self.prefix.in_range(ip) && !self.is_reserved(ip) && !used.contains(ip)
+    }
+
+    fn next_free(&self, used: &HashSet<IpAddr>) -> Option<IpAddr> {
+        match &self.prefix {
+            net::IpAddrCidr::V4(v4) => v4.hosts()
+                .map(IpAddr::V4)
+                .find(|ip| !self.is_reserved(ip) && !used.contains(ip)),
+            net::IpAddrCidr::V6(v6) => v6.hosts()
+                .map(IpAddr::V6)
+                .find(|ip| !self.is_reserved(ip) && !used.contains(ip))
+        }
+    }
+}
+
+/// a pool address that was requested by a record and will be filled in once
+/// every static address is known
+struct Pending {
+    zone: String,
+    index: usize,
+    pool: String,
+    owner: String,
+    name: String,
+    ttl: usize,
+    reverse: bool
+}
+
+/// the allocations of a run that are written to the lock file once every
+/// zone was written
+pub struct Allocations {
+    lock_path: PathBuf,
+    lock: LockMap
+}
+
+impl Allocations {
+
+    pub fn save(&self) -> error::Result<()> {
+        deploy::write(&self.lock_path, &serde_json::to_vec_pretty(&self.lock)?)
+    }
+}
+
+/// hands out addresses from the pools defined in a config. allocations are
+/// stored in a lock file so that the same owner gets the same address on
+/// every run. allocations of owners that are not requested anymore are kept,
+/// and their addresses are not handed out, unless prune is set
+pub struct Allocator {
+    lock_path: PathBuf,
+    prune: bool,
+    pools: HashMap<String, Pool>,
+    locked: LockMap,
+    pending: Vec<Pending>,
+    owner_counts: HashMap<(String, String), usize>
+}
+
+impl Allocator {
+
+    pub fn new(lock_path: PathBuf, prune: bool) -> error::Result<Allocator> {
+        let locked = if lock_path.is_file() {
+            serde_json::from_reader(std::io::BufReader::new(std::fs::File::open(&lock_path)?))?
+        } else {
+            LockMap::new()
+        };
+
+        Ok(Allocator {
+            lock_path,
+            prune,
+            pools: HashMap::new(),
+            locked,
+            pending: Vec::new(),
+            owner_counts: HashMap::new()
+        })
+    }
+
+    fn pool_key(zone: Option<&String>, name: &str) -> String {
+        match zone {
+            Some(zone) => format!("{}/{}", zone, name),
+            None => name.to_owned()
+        }
+    }
+
+    /// adds the pools of a zone, or of the config when no zone is given
+    pub fn add_pools(&mut self, zone: Option<&String>, pools: Option<config::PoolsMap>) -> error::Result<()> {
+        for (name, pool) in pools.unwrap_or_default() {
+            let key = Allocator::pool_key(zone, &name);
+            let pool = Pool::new(name, pool)?;
+
+            self.pools.insert(key, pool);
+        }
+
+        Ok(())
+    }
+
+    /// adds a placeholder record to the current zone of the storage that
+    /// will receive an address from the requested pool
+    pub fn request(
+        &mut self,
+        storage: &mut dns::ZoneStorage,
+        zone: &context::ZoneContext,
+        subdomain: &context::SubDomainContext,
+        pool: &str,
+        v4: bool,
+        reverse: bool
+    ) -> error::Result<()> {
+        let name = subdomain.get_domain();
+        let ttl = zone.get_ttl();
+        let zone_key = Allocator::pool_key(Some(zone.get_name_ref()), pool);
+        let key = if self.pools.contains_key(&zone_key) {
+            zone_key
+        } else if self.pools.contains_key(pool) {
+            pool.to_owned()
+        } else {
+            return Err(error::RuntimeError::ConfigError(format!("unknown address pool requested: \"{}\"", pool)));
+        };
+
+        if self.pools[&key].is_v4() != v4 {
+            return Err(error::RuntimeError::ConfigError(format!(
+                "pool \"{}\" ({}) cannot provide {} addresses",
+                pool, self.pools[&key].prefix, if v4 { "ipv4" } else { "ipv6" }
+            )));
+        }
+
+        let count = self.owner_counts.entry((key.clone(), name.clone())).or_insert(0);
+        *count += 1;
+
+        let owner = if *count == 1 {
+            name.clone()
+        } else {
+            format!("{}#{}", name, count)
+        };
+
+        let (zone, index) = storage.add_placeholder().ok_or_else(|| error::RuntimeError::ConfigError(
+            format!("no zone available for pool address of {}", name)
+        ))?;
+
+        self.pending.push(Pending {
+            zone, index,
+            pool: key,
+            owner, name, ttl, reverse
+        });
+
+        Ok(())
+    }
+
+    /// fills in every requested pool address and returns the allocations to
+    /// store in the lock file. addresses already used by records in the
+    /// storage are skipped and previous allocations are kept when they are
+    /// still available
+    pub fn allocate(self, storage: &mut dns::ZoneStorage) -> error::Result<Option<Allocations>> {
+        if self.pending.is_empty() && !self.lock_path.is_file() {
+            return Ok(None);
+        }
+
+        let mut used: HashSet<IpAddr> = HashSet::new();
+
+        for zone in storage.get_zones_ref().values() {
+            for record in zone.get_records_ref() {
+                match record {
+                    dns::record::Record::A {address, ..} => { used.insert(IpAddr::V4(*address)); },
+                    dns::record::Record::Aaaa {address, ..} => { used.insert(IpAddr::V6(*address)); },
+                    _ => {}
+                }
+            }
+        }
+
+        let mut lock = if self.prune {
+            LockMap::new()
+        } else {
+            let requested: HashSet<(&String, &String)> = self.pending.iter()
+                .map(|pending| (&pending.pool, &pending.owner))
+                .collect();
+
+            for (pool, owners) in &self.locked {
+                for (owner, ip) in owners {
+                    if !requested.contains(&(pool, owner)) {
+                        used.insert(*ip);
+                    }
+                }
+            }
+
+            self.locked.clone()
+        };
+
+        let mut assigned: Vec<Option<IpAddr>> = Vec::with_capacity(self.pending.len());
+
+        // keep previous allocations first so new owners cannot take them
+        for pending in &self.pending {
+            let pool = &self.pools[&pending.pool];
+            let previous = self.locked.get(&pending.pool)
+                .and_then(|owners| owners.get(&pending.owner))
+                .filter(|ip| pool.is_available(ip, &used));
+
+            if let Some(ip) = previous {
+                used.insert(*ip);
+            }
+
+            assigned.push(previous.copied());
+        }
+
+        for (pending, previous) in self.pending.iter().zip(assigned) {
+            let pool = &self.pools[&pending.pool];
+            let ip = match previous {
+                Some(ip) => ip,
+                None => {
+                    let ip = pool.next_free(&used).ok_or_else(|| error::RuntimeError::ConfigError(format!(
+                        "address pool \"{}\" ({}) is exhausted. failed to allocate for {}",
+                        pool.name, pool.prefix, pending.name
+                    )))?;
+                    used.insert(ip);
+                    ip
+                }
+            };
+
+            lock.entry(pending.pool.clone()).or_default().insert(pending.owner.clone(), ip);
+
+            let record = match ip {
+                IpAddr::V4(v4) => {
+                    if pending.reverse {
+                        storage.add_v4_reverse_record(&v4, dns::record::Record::Ptr {
+                            name: dns::ipv4_reverse_string(&v4, true)?,
+                            ttl: pending.ttl,
+                            domain: pending.name.clone()
+                        });
+                    }
+
+                    dns::record::Record::A {
+                        name: pending.name.clone(),
+                        ttl: pending.ttl,
+                        address: v4
+                    }
+                },
+                IpAddr::V6(v6) => {
+                    if pending.reverse {
+                        storage.add_v6_reverse_record(&v6, dns::record::Record::Ptr {
+                            name: dns::ipv6_reverse_string(&v6, true)?,
+                            ttl: pending.ttl,
+                            domain: pending.name.clone()
+                        });
+                    }
+
+                    dns::record::Record::Aaaa {
+                        name: pending.name.clone(),
+                        ttl: pending.ttl,
+                        address: v6
+                    }
+                }
+            };
+
+            storage.replace_record(&pending.zone, pending.index, record);
+        }
+
+        Ok(Some(Allocations {
+            lock_path: self.lock_path,
+            lock
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path};
+
+    use super::*;
+
+    fn pool(prefix: &str, reserved: &[&str]) -> config::Pool {
+        config::Pool {
+            prefix: prefix.parse().unwrap(),
+            reserved: Some(reserved.iter().map(|r| r.to_string()).collect())
+        }
+    }
+
+    fn ip(given: &str) -> IpAddr {
+        given.parse().unwrap()
+    }
+
+    fn lock_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("dns-zones-builder-{}-{}.lock", std::process::id(), name));
+        let _ = std::fs::remove_file(&path);
+
+        path
+    }
+
+    /// builds an allocator for a /29 pool and requests an address for every
+    /// given owner
+    fn allocator(path: &Path, owners: &[&str], storage: &mut dns::ZoneStorage) -> Allocator {
+        let mut pools = config::PoolsMap::new();
+        pools.insert("lab".to_owned(), pool("10.0.0.0/29", &["10.0.0.1"]));
+
+        let mut allocator = Allocator::new(path.to_path_buf(), false).unwrap();
+        allocator.add_pools(None, Some(pools)).unwrap();
+
+        storage.add_zone(dns::zone::Zone::new("lab.example".to_owned(), "lab.example.".to_owned()));
+
+        for owner in owners {
+            let (zone, index) = storage.add_placeholder().unwrap();
+
+            allocator.pending.push(Pending {
+                zone, index,
+                pool: "lab".to_owned(),
+                owner: owner.to_string(),
+                name: owner.to_string(),
+                ttl: 300,
+                reverse: false
+            });
+        }
+
+        allocator
+    }
+
+    fn addresses(storage: &dns::ZoneStorage) -> Vec<(String, IpAddr)> {
+        storage.get_zones_ref()["lab.example"].get_records_ref().iter()
+            .filter_map(|record| match record {
+                dns::record::Record::A {name, address, ..} => Some((name.clone(), IpAddr::V4(*address))),
+                _ => None
+            })
+            .collect()
+    }
+
+    #[test]
+    fn reserved_ranges() {
+        let lab = Pool::new("lab".to_owned(), pool("10.0.0.0/24", &["10.0.0.1", "10.0.0.10 - 10.0.0.19", "10.0.0.128/26"]))
+            .unwrap();
+
+        assert!(lab.is_reserved(&ip("10.0.0.1")));
+        assert!(lab.is_reserved(&ip("10.0.0.15")));
+        assert!(lab.is_reserved(&ip("10.0.0.191")));
+        assert!(!lab.is_reserved(&ip("10.0.0.20")));
+        assert!(!lab.is_available(&ip("10.0.1.2"), &HashSet::new()));
+
+        let used = vec!(ip("10.0.0.2")).into_iter().collect();
+        assert_eq!(lab.next_free(&used), Some(ip("10.0.0.3")));
+
+        assert!(Pool::new("lab".to_owned(), pool("10.0.0.0/24", &["10.0.1.1"])).is_err());
+        assert!(Pool::new("lab".to_owned(), pool("10.0.0.0/24", &["10.0.0.9-10.0.0.2"])).is_err());
+        assert!(Pool::new("lab".to_owned(), pool("10.0.0.0/24", &["nope"])).is_err());
+    }
+
+    #[test]
+    fn lock_file() {
+        let path = lock_path("lock-file");
+
+        let mut storage = dns::ZoneStorage::new();
+        let allocations = allocator(&path, &["a", "b"], &mut storage).allocate(&mut storage)
+            .unwrap()
+            .unwrap();
+        allocations.save().unwrap();
+
+        assert_eq!(addresses(&storage), vec!(("a".to_owned(), ip("10.0.0.2")), ("b".to_owned(), ip("10.0.0.3"))));
+
+        // previous allocations are kept even when the order changes and the
+        // address of a dropped owner is not handed out
+        let mut storage = dns::ZoneStorage::new();
+        allocator(&path, &["c", "b"], &mut storage).allocate(&mut storage)
+            .unwrap()
+            .unwrap()
+            .save()
+            .unwrap();
+
+        assert_eq!(addresses(&storage), vec!(("c".to_owned(), ip("10.0.0.4")), ("b".to_owned(), ip("10.0.0.3"))));
+
+        let locked: LockMap = serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+        assert_eq!(locked["lab"].keys().collect::<Vec<_>>(), vec!("a", "b", "c"));
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn exhausted() {
+        let path = lock_path("exhausted");
+
+        let mut storage = dns::ZoneStorage::new();
+        let owners = ["a", "b", "c", "d", "e", "f"];
+        let result = allocator(&path, &owners, &mut storage).allocate(&mut storage);
+
+        assert!(result.err().unwrap().get_msg().contains("address pool \"lab\" (10.0.0.0/29) is exhausted"));
+        assert!(!path.exists());
+    }
+}
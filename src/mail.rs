@@ -0,0 +1,386 @@
+use std::collections::{HashMap};
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::str::{FromStr};
+
+use base64::{Engine};
+use base64::engine::general_purpose::{STANDARD as BASE64};
+
+use crate::config;
+use crate::context;
+use crate::dns;
+use crate::error;
+
+/// max number of dns lookups a single spf evaluation may cause. RFC 7208 4.6.4
+const SPF_LOOKUP_LIMIT: usize = 10;
+
+/// der prefix of an ed25519 SubjectPublicKeyInfo, the raw key follows it
+const ED25519_SPKI_PREFIX: [u8; 12] = [
+    0x30, 0x2a, 0x30, 0x05, 0x06, 0x03, 0x2b, 0x65, 0x70, 0x03, 0x21, 0x00
+];
+
+/// checks an spf network. only the address and the prefix length are checked
+/// since spf allows host bits to be set
+fn valid_network<A: FromStr>(given: &str, max_cidr: u8) -> bool {
+    match given.split_once('/') {
+        Some((addr, cidr)) => addr.parse::<A>().is_ok() &&
+            !cidr.is_empty() && cidr.bytes().all(|b| b.is_ascii_digit()) &&
+            cidr.parse::<u8>().is_ok_and(|cidr| cidr <= max_cidr),
+        None => given.parse::<A>().is_ok()
+    }
+}
+
+fn spf_domain(given: &str) -> error::Result<String> {
+    let trimmed = given.trim_end_matches('.');
+
+    if trimmed.is_empty() || trimmed.contains(char::is_whitespace) {
+        return Err(error::RuntimeError::ConfigError(format!("invalid spf domain given: \"{}\"", given)));
+    }
+
+    dns::domain_to_ascii(trimmed).map_err(|label| error::RuntimeError::ConfigError(
+        format!("invalid domain label \"{}\" in spf domain \"{}\"", label, given)
+    ))
+}
+
+fn dmarc_uri(given: String) -> error::Result<String> {
+    let uri = if given.starts_with("mailto:") {
+        given
+    } else {
+        format!("mailto:{}", given)
+    };
+
+    match uri["mailto:".len()..].split_once('@') {
+        Some((local, domain)) if !local.is_empty() && !domain.is_empty() &&
+            !uri.contains(|c: char| c == ',' || c == ';' || c.is_whitespace()) => Ok(uri),
+        _ => Err(error::RuntimeError::ConfigError(format!("invalid dmarc report address given: \"{}\"", uri)))
+    }
+}
+
+/// builds the TXT data for an spf record
+pub fn spf_string(detail: config::SpfDetail) -> error::Result<String> {
+    let mut rtn = "v=spf1".to_owned();
+
+    if detail.a.unwrap_or(false) {
+        rtn.push_str(" a");
+    }
+
+    if detail.mx.unwrap_or(false) {
+        rtn.push_str(" mx");
+    }
+
+    for ip4 in detail.ip4.unwrap_or_default() {
+        if !valid_network::<Ipv4Addr>(&ip4, 32) {
+            return Err(error::RuntimeError::ConfigError(format!("invalid spf ip4 network given: \"{}\"", ip4)));
+        }
+
+        rtn.push_str(" ip4:");
+        rtn.push_str(&ip4);
+    }
+
+    for ip6 in detail.ip6.unwrap_or_default() {
+        if !valid_network::<Ipv6Addr>(&ip6, 128) {
+            return Err(error::RuntimeError::ConfigError(format!("invalid spf ip6 network given: \"{}\"", ip6)));
+        }
+
+        rtn.push_str(" ip6:");
+        rtn.push_str(&ip6);
+    }
+
+    for include in detail.include.unwrap_or_default() {
+        rtn.push_str(" include:");
+        rtn.push_str(&spf_domain(&include)?);
+    }
+
+    if let Some(all) = detail.all {
+        if detail.redirect.is_some() {
+            return Err(error::RuntimeError::ConfigError(
+                "spf redirect has no effect when an all policy is given".to_owned()
+            ));
+        }
+
+        rtn.push_str(match all {
+            config::SpfAll::Pass => " +all",
+            config::SpfAll::Neutral => " ?all",
+            config::SpfAll::SoftFail => " ~all",
+            config::SpfAll::Fail => " -all"
+        });
+    } else if let Some(redirect) = detail.redirect {
+        rtn.push_str(" redirect=");
+        rtn.push_str(&spf_domain(&redirect)?);
+    }
+
+    Ok(rtn)
+}
+
+fn read_dkim_key(config: &context::ConfigContext, detail: &config::DkimDetail) -> error::Result<String> {
+    let contents = match (&detail.key, &detail.key_file) {
+        (Some(key), None) => key.clone(),
+        (None, Some(key_file)) => std::fs::read_to_string(config.resolve_path(key_file))?,
+        _ => return Err(error::RuntimeError::ConfigError(format!(
+            "dkim selector \"{}\" requires exactly one of key or key_file", detail.selector
+        )))
+    };
+
+    if contents.contains("PRIVATE KEY-----") {
+        return Err(error::RuntimeError::ConfigError(format!(
+            "dkim selector \"{}\" was given a private key", detail.selector
+        )));
+    }
+
+    let mut encoded = String::with_capacity(contents.len());
+    let mut in_pem = false;
+
+    for line in contents.lines() {
+        let line = line.trim();
+
+        if line.starts_with("-----BEGIN") {
+            in_pem = true;
+            encoded.clear();
+        } else if line.starts_with("-----END") {
+            break;
+        } else if in_pem || !contents.contains("-----BEGIN") {
+            encoded.extend(line.chars().filter(|c| !c.is_whitespace()));
+        }
+    }
+
+    Ok(encoded)
+}
+
+/// builds the TXT data for a dkim record. the public key is read from the
+/// config or from a pem/base64 file relative to the config file
+pub fn dkim_string(config: &context::ConfigContext, detail: &config::DkimDetail) -> error::Result<String> {
+    let encoded = read_dkim_key(config, detail)?;
+    let decoded = BASE64.decode(encoded.as_bytes()).map_err(|e| error::RuntimeError::ConfigError(format!(
+        "invalid dkim public key for selector \"{}\". {}", detail.selector, e
+    )))?;
+
+    let (key_type, key) = match detail.key_type.as_ref().unwrap_or(&config::DkimKeyType::Rsa) {
+        config::DkimKeyType::Rsa => ("rsa", decoded.as_slice()),
+        config::DkimKeyType::Ed25519 => {
+            if decoded.len() == 44 && decoded.starts_with(&ED25519_SPKI_PREFIX) {
+                ("ed25519", &decoded[ED25519_SPKI_PREFIX.len()..])
+            } else if decoded.len() == 32 {
+                ("ed25519", decoded.as_slice())
+            } else {
+                return Err(error::RuntimeError::ConfigError(format!(
+                    "invalid ed25519 public key for selector \"{}\"", detail.selector
+                )));
+            }
+        }
+    };
+
+    if key.is_empty() {
+        return Err(error::RuntimeError::ConfigError(format!(
+            "empty dkim public key for selector \"{}\"", detail.selector
+        )));
+    }
+
+    let mut rtn = format!("v=DKIM1; k={}; ", key_type);
+
+    if detail.testing.unwrap_or(false) {
+        rtn.push_str("t=y; ");
+    }
+
+    rtn.push_str("p=");
+    rtn.push_str(&BASE64.encode(key));
+
+    Ok(rtn)
+}
+
+/// returns the owner name of a dkim record for the given selector
+pub fn dkim_domain(selector: &str, domain: &str) -> error::Result<String> {
+    if selector.is_empty() || selector.split('.').any(|label| label.is_empty() || label.len() > 63) {
+        return Err(error::RuntimeError::ConfigError(format!("invalid dkim selector given: \"{}\"", selector)));
+    }
+
+    Ok(format!("{}._domainkey.{}", selector, domain))
+}
+
+/// builds the TXT data for a dmarc record
+pub fn dmarc_string(detail: config::DmarcDetail) -> error::Result<String> {
+    fn policy_str(policy: config::DmarcPolicy) -> &'static str {
+        match policy {
+            config::DmarcPolicy::None => "none",
+            config::DmarcPolicy::Quarantine => "quarantine",
+            config::DmarcPolicy::Reject => "reject"
+        }
+    }
+
+    fn alignment_str(alignment: config::DmarcAlignment) -> &'static str {
+        match alignment {
+            config::DmarcAlignment::Relaxed => "r",
+            config::DmarcAlignment::Strict => "s"
+        }
+    }
+
+    let mut rtn = format!("v=DMARC1; p={}", policy_str(detail.policy));
+
+    if let Some(policy) = detail.subdomain_policy {
+        rtn.push_str("; sp=");
+        rtn.push_str(policy_str(policy));
+    }
+
+    if let Some(pct) = detail.pct {
+        if pct > 100 {
+            return Err(error::RuntimeError::ConfigError(format!(
+                "dmarc pct must be between 0 and 100. given: {}", pct
+            )));
+        }
+
+        rtn.push_str(&format!("; pct={}", pct));
+    }
+
+    for (tag, list) in [("rua", detail.rua), ("ruf", detail.ruf)] {
+        if let Some(uris) = list {
+            let mut converted = Vec::with_capacity(uris.len());
+
+            for uri in uris {
+                converted.push(dmarc_uri(uri)?);
+            }
+
+            rtn.push_str(&format!("; {}={}", tag, converted.join(",")));
+        }
+    }
+
+    if let Some(alignment) = detail.adkim {
+        rtn.push_str("; adkim=");
+        rtn.push_str(alignment_str(alignment));
+    }
+
+    if let Some(alignment) = detail.aspf {
+        rtn.push_str("; aspf=");
+        rtn.push_str(alignment_str(alignment));
+    }
+
+    Ok(rtn)
+}
+
+/// returns the owner name of a dmarc record
+pub fn dmarc_domain(domain: &str) -> String {
+    format!("_dmarc.{}", domain)
+}
+
+fn count_spf_lookups<'a>(
+    records: &'a HashMap<String, Vec<String>>,
+    domain: &'a str,
+    visiting: &mut Vec<&'a str>
+) -> error::Result<usize> {
+    let terms = match records.get(domain) {
+        Some(terms) => terms,
+        None => return Ok(0)
+    };
+
+    if visiting.contains(&domain) {
+        return Err(error::RuntimeError::ConfigError(format!(
+            "spf include loop detected: {} -> {}", visiting.join(" -> "), domain
+        )));
+    }
+
+    visiting.push(domain);
+
+    let mut count = 0;
+
+    for term in terms {
+        let term = term.trim_start_matches(['+', '-', '~', '?']);
+        let (name, target) = match term.find([':', '=', '/']) {
+            Some(index) => (&term[..index], Some(&term[index + 1..])),
+            None => (term, None)
+        };
+
+        match name.to_ascii_lowercase().as_str() {
+            "include" | "redirect" => {
+                count += 1;
+
+                if let Some(target) = target {
+                    let key = dns::normalize_domain(target);
+
+                    if let Some((key, _)) = records.get_key_value(&key) {
+                        count += count_spf_lookups(records, key, visiting)?;
+                    }
+                }
+            },
+            "a" | "mx" | "ptr" | "exists" => count += 1,
+            _ => {}
+        }
+    }
+
+    visiting.pop();
+
+    Ok(count)
+}
+
+/// checks every spf record in the given storage against the dns lookup
+/// limit. includes of domains defined in the same storage are followed
+pub fn check_spf_lookups(storage: &dns::ZoneStorage) -> error::Result<()> {
+    let mut records: HashMap<String, Vec<String>> = HashMap::new();
+
+    for zone in storage.get_zones_ref().values() {
+        for record in zone.get_records_ref() {
+            if let dns::record::Record::Txt {name, value, ..} = record {
+                let joined = value.joined();
+                let mut terms = joined.split_whitespace();
+
+                if !terms.next().is_some_and(|v| v.eq_ignore_ascii_case("v=spf1")) {
+                    continue;
+                }
+
+                let key = dns::normalize_domain(name);
+
+                if records.contains_key(&key) {
+                    return Err(error::RuntimeError::ConfigError(format!(
+                        "multiple spf records found for {}", name
+                    )));
+                }
+
+                records.insert(key, terms.map(|t| t.to_owned()).collect());
+            }
+        }
+    }
+
+    for domain in records.keys() {
+        let count = count_spf_lookups(&records, domain, &mut vec!())?;
+
+        if count > SPF_LOOKUP_LIMIT {
+            return Err(error::RuntimeError::ConfigError(format!(
+                "spf record for {} requires {} dns lookups. limit is {}", domain, count, SPF_LOOKUP_LIMIT
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spf_records(records: &[(&str, &str)]) -> HashMap<String, Vec<String>> {
+        records.iter()
+            .map(|(domain, terms)| (domain.to_string(), terms.split_whitespace().map(|t| t.to_owned()).collect()))
+            .collect()
+    }
+
+    #[test]
+    fn spf_lookups() {
+        let records = spf_records(&[
+            ("example.com.", "ip4:192.0.2.0/24 a mx:mail.example.com -ptr include:_spf.example.com ~all"),
+            ("_spf.example.com.", "a/24 exists:%{i}.example.com include:_spf.other.net redirect=_spf2.example.com"),
+            ("_spf2.example.com.", "ip6:2001:db8::/32 MX -all")
+        ]);
+
+        assert_eq!(count_spf_lookups(&records, "_spf2.example.com.", &mut vec!()).unwrap(), 1);
+        // the include of another domain counts but cannot be followed
+        assert_eq!(count_spf_lookups(&records, "_spf.example.com.", &mut vec!()).unwrap(), 5);
+        assert_eq!(count_spf_lookups(&records, "example.com.", &mut vec!()).unwrap(), 9);
+        assert_eq!(count_spf_lookups(&records, "missing.example.com.", &mut vec!()).unwrap(), 0);
+    }
+
+    #[test]
+    fn spf_include_loop() {
+        let records = spf_records(&[
+            ("a.example.", "include:b.example -all"),
+            ("b.example.", "include:A.example. -all")
+        ]);
+
+        assert!(count_spf_lookups(&records, "a.example.", &mut vec!()).is_err());
+    }
+}
@@ -1,17 +1,30 @@
 use std::io::prelude::*;
 
-use lazy_static::lazy_static;
-use regex::{Regex};
+use log::{debug, info, warn};
 
 mod error;
 mod net;
 mod dns;
 mod config;
 mod context;
-
-lazy_static! {
-    static ref KEY_REGEX: Regex = Regex::new(r"\{([_0-9a-zA-Z]+)}").unwrap();
-}
+mod mail;
+mod changes;
+mod fingerprint;
+mod ipam;
+mod conflicts;
+mod delegation;
+mod deploy;
+mod fill;
+mod extend;
+mod hooks;
+mod profiles;
+mod range;
+mod report;
+mod schema;
+mod soa;
+mod template;
+mod views;
+mod watch;
 
 type ZonePreBuilt = (
     String, // zone name
@@ -19,11 +32,16 @@ type ZonePreBuilt = (
     Vec<config::SubDomain>
 );
 
+type ZoneHooks = std::collections::HashMap<
+    String, // zone name
+    Vec<config::Hook>
+>;
+
 fn main() {
     std::process::exit(match app_runner() {
         Ok(code) => code,
         Err(err) => {
-            println!("{}", err);
+            eprintln!("{}", err);
 
             err.get_code()
         }
@@ -76,12 +94,101 @@ fn v6_test() {
     println!("  finish_hex: {:0>32x}", finish);
 }
 
+/// options given on the command line that apply to every build
+struct BuildOptions {
+    profile: Option<String>,
+
+    /// when disabled zones are only checked and nothing is written
+    write: bool,
+
+    /// drops pool allocations of owners that are not requested anymore
+    prune_allocations: bool
+}
+
 fn app_runner() -> error::Result<i32> {
     let mut files: Vec<std::path::PathBuf> = vec!();
-    let mut args = std::env::args();
-    args.next();
+    let mut options = BuildOptions {
+        profile: None,
+        write: true,
+        prune_allocations: false
+    };
+    let mut report: Option<report::Report> = None;
+    let mut verbosity = 0;
+    let mut args = std::env::args().skip(1).peekable();
+
+    if args.next_if(|arg| arg == "schema").is_some() {
+        if let Some(arg) = args.next() {
+            return Err(error::RuntimeError::InvalidArgument(
+                format!("the schema command takes no arguments. given: {}", arg)
+            ));
+        }
+
+        println!("{}", schema::generate()?);
+
+        return Ok(0);
+    }
+
+    let watch = args.next_if(|arg| arg == "watch").is_some();
+
+    if watch {
+        options.write = false;
+    }
 
     while let Some(arg) = args.next() {
+        if arg == "--profile" {
+            options.profile = Some(args.next().ok_or_else(|| error::RuntimeError::InvalidArgument(
+                "--profile requires a profile name".to_owned()
+            ))?);
+            continue;
+        } else if let Some(name) = arg.strip_prefix("--profile=") {
+            options.profile = Some(name.to_owned());
+            continue;
+        } else if arg == "--write" {
+            if !watch {
+                return Err(error::RuntimeError::InvalidArgument(
+                    "--write is only available for the watch command".to_owned()
+                ));
+            }
+
+            options.write = true;
+            continue;
+        } else if arg == "--report" || arg.starts_with("--report=") {
+            let format = match arg.strip_prefix("--report=") {
+                Some(format) => format.to_owned(),
+                None => args.next().ok_or_else(|| error::RuntimeError::InvalidArgument(
+                    "--report requires a format".to_owned()
+                ))?
+            };
+
+            if format != "json" {
+                return Err(error::RuntimeError::InvalidArgument(
+                    format!("unknown report format \"{}\". only json is available", format)
+                ));
+            }
+
+            if watch {
+                return Err(error::RuntimeError::InvalidArgument(
+                    "--report is not available for the watch command".to_owned()
+                ));
+            }
+
+            report = Some(report::Report::default());
+            report::capture_warnings();
+            continue;
+        } else if arg == "--prune-allocations" {
+            options.prune_allocations = true;
+            continue;
+        } else if arg == "-v" || arg == "--verbose" {
+            verbosity += 1;
+            continue;
+        } else if arg == "-vv" {
+            verbosity += 2;
+            continue;
+        } else if arg == "-q" || arg == "--quiet" {
+            verbosity -= 1;
+            continue;
+        }
+
         if let Ok(canonical_path) = std::fs::canonicalize(arg.clone()) {
             if !canonical_path.is_file() {
                 return Err(error::RuntimeError::InvalidFile(canonical_path.into_os_string()));
@@ -93,150 +200,544 @@ fn app_runner() -> error::Result<i32> {
         }
     }
 
+    report::init_logger(match verbosity {
+        i32::MIN..=-2 => log::LevelFilter::Off,
+        -1 => log::LevelFilter::Error,
+        0 => log::LevelFilter::Info,
+        1 => log::LevelFilter::Debug,
+        _ => log::LevelFilter::Trace
+    });
+
+    if watch {
+        return watch::run(files, |file, read| {
+            build_file(file, &options, read, &mut report::Report::default())
+        });
+    }
+
+    let mut report = match report {
+        Some(report) => report,
+        None => {
+            for file in files {
+                build_file(&file, &options, &mut Vec::new(), &mut report::Report::default())?;
+            }
+
+            return Ok(0);
+        }
+    };
+    let mut result = Ok(0);
+
     for file in files {
-        let mut new_zones = dns::ZoneStorage::new();
-        let mut conf = load_file(file.clone())?;
-        let mut pre_builts: Vec<ZonePreBuilt> = Vec::with_capacity(conf.zones.len());
-        let zones = std::mem::take(&mut conf.zones);
-        let conf_context = context::ConfigContext::new(conf);
+        if let Err(err) = build_file(&file, &options, &mut Vec::new(), &mut report) {
+            report.errors.push(report::FileError {
+                config: file,
+                error: err.to_string()
+            });
+
+            if result.is_ok() {
+                result = Err(err);
+            } else {
+                log::error!("{}", err);
+            }
+        }
+    }
+
+    report.warnings = report::take_warnings();
+
+    let stdout = std::io::stdout();
+    let mut handle = stdout.lock();
+
+    serde_json::to_writer_pretty(&mut handle, &report)?;
+    writeln!(handle)?;
+
+    result
+}
+
+/// a zone that was built and validated but is not written yet
+struct BuiltZone {
+    zone: dns::zone::Zone,
+    path: std::path::PathBuf,
+    previous: Option<Vec<u8>>,
+
+    /// the new contents of the zone file. none when the zone did not change
+    pending: Option<deploy::PendingFile>,
+    hooks: Vec<config::Hook>,
+    report: report::ZoneReport
+}
+
+/// the zones of a config, or of one view of a config, that are ready to be
+/// written together with their pool allocations
+struct BuiltZones {
+    zones: Vec<BuiltZone>,
+    allocations: Option<ipam::Allocations>,
+
+    /// names of the zones that failed validation
+    failed: Vec<String>
+}
+
+impl BuiltZones {
+
+    /// the origin and path of the zone file of every zone
+    fn zone_files(&self) -> Vec<(String, std::path::PathBuf)> {
+        let mut rtn: Vec<(String, std::path::PathBuf)> = self.zones.iter()
+            .map(|built| (built.zone.get_origin_ref().clone(), built.path.clone()))
+            .collect();
+
+        rtn.sort();
+
+        rtn
+    }
+}
+
+/// builds every zone of a config file. the config file and every file read
+/// while building are added to the read list and every zone handled is
+/// added to the report. zones are only written once every zone of every
+/// view passed validation so that a failed build leaves the existing files
+/// untouched
+fn build_file(
+    file: &std::path::Path,
+    options: &BuildOptions,
+    read: &mut Vec<std::path::PathBuf>,
+    report: &mut report::Report
+) -> error::Result<()> {
+    read.push(file.to_path_buf());
+
+    let mut conf = load_file(file.to_path_buf())?;
+    let directory = std::path::PathBuf::from(match conf.directory.as_deref() {
+        Some(directory) if !directory.is_empty() => directory,
+        _ => "."
+    });
+    let backups = conf.backups.unwrap_or(0);
+    let _lock = if options.write {
+        Some(deploy::DirectoryLock::acquire(&directory)?)
+    } else {
+        None
+    };
+    let views = conf.views.take();
+    let targets: Vec<Option<&config::View>> = match &views {
+        Some(views) => views.iter().map(Some).collect(),
+        None => vec!(None)
+    };
+    let mut built: Vec<BuiltZones> = Vec::with_capacity(targets.len());
+
+    for view in targets {
+        match build_zones(conf.clone(), file, options, view.zip(views.as_ref()), read) {
+            Ok(zones) => built.push(zones),
+            Err(err) => {
+                add_reports(built, report);
+
+                return Err(err);
+            }
+        }
+    }
 
-        new_zones.reserve(zones.len());
+    let mut failed: Vec<String> = built.iter().flat_map(|zones| zones.failed.iter().cloned()).collect();
 
-        // first pass
-        for mut zone in zones {
-            let reverse_zone_info = std::mem::take(&mut zone.reverse_zone);
-            let subdomains = std::mem::take(&mut zone.subdomains);
-            let mut zone_context = context::ZoneContext::new(zone);
+    let result = if !failed.is_empty() {
+        failed.sort();
 
-            if new_zones.has_zone(zone_context.get_name_ref()) {
-                println!("duplicate zone name encountered. name: \"{}\"", zone_context.get_name_ref());
+        Err(error::RuntimeError::Error(format!(
+            "zones failed validation: {}", failed.join(", ")
+        )))
+    } else if options.write {
+        write_zones(&mut built, &directory, &conf.name, views.as_deref(), backups)
+    } else {
+        Ok(())
+    };
+
+    add_reports(built, report);
+
+    result
+}
+
+fn add_reports(built: Vec<BuiltZones>, report: &mut report::Report) {
+    for zones in built {
+        report.zones.extend(zones.zones.into_iter().map(|built| built.report));
+    }
+}
+
+/// writes every changed zone, the pool allocations and the view blocks and
+/// then runs the hooks of the zones that were written. a failing hook does
+/// not stop the hooks of the other zones, the build fails afterwards
+fn write_zones(
+    built: &mut [BuiltZones],
+    directory: &std::path::Path,
+    name: &str,
+    views: Option<&[config::View]>,
+    backups: usize
+) -> error::Result<()> {
+    for zones in built.iter_mut() {
+        for built in &mut zones.zones {
+            if let Some(pending) = built.pending.take() {
+                pending.commit(backups)?;
+
+                info!("zone {}: {}", built.zone.get_name_ref(), built.report.change);
+
+                built.report.status = report::ZoneStatus::Written;
+            }
+        }
+
+        if let Some(allocations) = zones.allocations.take() {
+            allocations.save()?;
+        }
+    }
+
+    if let Some(views) = views {
+        let conf_path = directory.join(format!("{}.views.conf", name));
+        let zone_files: Vec<Vec<(String, std::path::PathBuf)>> = built.iter()
+            .map(|zones| zones.zone_files())
+            .collect();
+
+        views::write_named_conf(&conf_path, views, &zone_files)?;
+    }
+
+    let mut failed: Vec<String> = Vec::new();
+
+    for zones in built.iter_mut() {
+        for built in &mut zones.zones {
+            if built.report.status != report::ZoneStatus::Written {
                 continue;
             }
 
-            if let Some(info) = reverse_zone_info {
-                match info.r#type {
-                    config::ReverseType::V4 => {
-                        let addr_cidr = net::Ipv4AddrCidr::from_addr(
-                            info.addr.parse::<std::net::Ipv4Addr>().map_err(|_| error::RuntimeError::Error(
-                                format!("given ipv4 is invalid for reverse zone. given: {}", info.addr)
-                            ))?,
-                            info.cidr
-                        ).map_err(|e| error::RuntimeError::Error(
-                            e.get_msg()
-                        ))?;
-
-                        zone_context.set_domain(dns::ipv4_reverse_prefix(&addr_cidr, true)?);
-
-                        new_zones.add_v4_rev_zone(
-                            dns::zone::Zone::new(
-                                zone_context.get_name(),
-                                zone_context.get_domain()
-                            ),
-                            addr_cidr
-                        );
-                    },
-                    config::ReverseType::V6 => {
-                        let addr_cidr = net::Ipv6AddrCidr::from_addr(
-                            info.addr.parse::<std::net::Ipv6Addr>().map_err(|_| error::RuntimeError::Error(
-                                format!("given ipv6 is invalid for reverse zone. given: {}", info.addr)
-                            ))?,
-                            info.cidr
-                        ).map_err(|e| error::RuntimeError::Error(
-                            e.get_msg()
-                        ))?;
-
-                        zone_context.set_domain(dns::ipv6_reverse_prefix(&addr_cidr, true)?);
-
-                        new_zones.add_v6_rev_zone(
-                            dns::zone::Zone::new(
-                                zone_context.get_name(),
-                                zone_context.get_domain()
-                            ),
-                            addr_cidr
-                        );
-                    }
-                }
-            } else {
-                new_zones.add_zone(dns::zone::Zone::new(
-                    zone_context.get_name(),
-                    zone_context.get_domain()
-                ));
+            let result = run_hooks(
+                &built.zone, &built.path, &built.hooks, built.previous.as_deref(), &mut built.report
+            );
+
+            if let Err(err) = result {
+                log::error!("{}", err);
+
+                failed.push(built.zone.get_name());
             }
+        }
+    }
+
+    if !failed.is_empty() {
+        failed.sort();
+
+        return Err(error::RuntimeError::Error(format!(
+            "hooks failed for zones: {}", failed.join(", ")
+        )));
+    }
+
+    Ok(())
+}
+
+/// builds and validates every zone of a config. the profile is applied
+/// before anything else is done and when a view is given only the
+/// subdomains and records of that view are used. zones that did not change
+/// are not written again. a zone failing validation does not stop the other
+/// zones from being validated. files read from the config are added to the
+/// read list even when the build fails
+fn build_zones(
+    mut conf: config::Config,
+    file: &std::path::Path,
+    options: &BuildOptions,
+    view: Option<(&config::View, &Vec<config::View>)>,
+    read: &mut Vec<std::path::PathBuf>
+) -> error::Result<BuiltZones> {
+    let profile = options.profile.as_deref();
+    let mut new_zones = dns::ZoneStorage::new();
+    let mut zones = extend::resolve_zones(conf.templates.take(), std::mem::take(&mut conf.zones))?;
+    let mut failed: Vec<String> = Vec::new();
 
-            pre_builts.push((zone_context.get_name(), zone_context, subdomains));
+    if let Some(profile) = profile {
+        profiles::apply(&mut conf, &mut zones, profile)?;
+    }
+
+    if let Some((view, views)) = view {
+        zones = views::filter_zones(zones, &view.name, views)?;
+    }
+
+    let mut allocator = ipam::Allocator::new(
+        lock_file_path(file, view.map(|(v, _)| &v.name)), options.prune_allocations
+    )?;
+    allocator.add_pools(None, std::mem::take(&mut conf.pools))?;
+    let conf_context = context::ConfigContext::new(conf, file);
+    let added = add_zones(&conf_context, zones, &mut allocator, &mut new_zones);
+
+    read.extend(conf_context.take_read_files());
+
+    let (reverse_fills, mut zone_hooks) = added?;
+    let allocations = allocator.allocate(&mut new_zones)?;
+
+    for reverse_fill in reverse_fills {
+        reverse_fill.apply(&mut new_zones)?;
+    }
+
+    delegation::add_delegations(&mut new_zones);
+    mail::check_spf_lookups(&new_zones)?;
+    conflicts::check_addresses(&mut new_zones, conf_context.get_address_conflicts())?;
+    soa::check_zones(&mut new_zones)?;
+
+    let mut built = Vec::with_capacity(new_zones.get_zones_ref().len());
+
+    for (_, mut zone) in new_zones.into_inner() {
+        debug!("handling zone: {}", zone.get_name());
+        let view_name = view.map(|(v, _)| v.name.as_str());
+        let path = views::zone_path(conf_context.get_directory_ref(), zone.get_name_ref(), view_name);
+        let previous = std::fs::read(&path).ok();
+        let details = changes::details(profile, view_name);
+        let status = changes::compare(&mut zone, &details, previous.as_deref());
+        let mut zone_report = report::ZoneReport::new(file, view_name, &zone, &path, status);
+        let hooks = zone_hooks.remove(zone.get_name_ref()).unwrap_or_default();
+
+        if status == changes::Status::Unchanged {
+            info!("zone {}: {}", zone.get_name_ref(), status);
+
+            zone_report.status = report::ZoneStatus::Unchanged;
+            built.push(BuiltZone { zone, path, previous, pending: None, hooks, report: zone_report });
+            continue;
         }
 
-        for (name, zone_context, subdomains) in pre_builts {
-            let mut first = true;
-            new_zones.set_current(name);
+        let mut contents = changes::header(&path, &details);
+        contents.push_str(&format!("{}\n", zone));
 
-            for mut subdomain in subdomains {
-                if !first {
-                    new_zones.add_record(dns::record::Record::Blank);
-                } else {
-                    first = false;
-                }
+        // zones that are only checked are staged outside of the destination
+        // directory, which may not be writable and is not locked
+        let pending = if options.write {
+            deploy::PendingFile::create(&path, contents.as_bytes())?
+        } else {
+            deploy::PendingFile::scratch(&path, contents.as_bytes())?
+        };
+        let cmd = std::process::Command::new("named-checkzone")
+            .arg(zone.get_origin_ref().as_str())
+            .arg(pending.get_tmp_path_ref())
+            .output()?;
 
-                let records = std::mem::take(&mut subdomain.records);
-                let subdomain_contex = context::SubDomainContext::new(&zone_context, subdomain);
+        zone_report.validation = String::from_utf8_lossy(&cmd.stdout).lines()
+            .chain(String::from_utf8_lossy(&cmd.stderr).lines())
+            .map(|line| line.to_owned())
+            .collect();
+
+        if !cmd.status.success() {
+            for line in &zone_report.validation {
+                log::error!("{}", line);
+            }
+
+            log::error!("zone {} failed validation", zone.get_name_ref());
+
+            failed.push(zone.get_name());
+        } else {
+            for line in &zone_report.validation {
+                debug!("{}", line);
+            }
+
+            if !options.write {
+                info!("zone {}: {} (not written)", zone.get_name_ref(), status);
+            }
+
+            zone_report.status = report::ZoneStatus::Checked;
+        }
+
+        built.push(BuiltZone {
+            zone, path, previous, pending: Some(pending).filter(|_| options.write), hooks, report: zone_report
+        });
+    }
+
+    Ok(BuiltZones {
+        zones: built,
+        allocations: allocations.filter(|_| options.write),
+        failed
+    })
+}
+
+/// adds the zones of a config and their records to the storage. returns the
+/// reverse fills to apply once every record is known and the hooks of every
+/// zone
+fn add_zones(
+    conf_context: &context::ConfigContext,
+    zones: Vec<config::Zone>,
+    allocator: &mut ipam::Allocator,
+    new_zones: &mut dns::ZoneStorage
+) -> error::Result<(Vec<fill::ReverseFill>, ZoneHooks)> {
+    let mut pre_builts: Vec<ZonePreBuilt> = Vec::with_capacity(zones.len());
+    let mut reverse_fills: Vec<fill::ReverseFill> = Vec::new();
+    let mut zone_hooks = ZoneHooks::new();
+
+    new_zones.reserve(zones.len());
+
+    // first pass
+    for mut zone in zones {
+        let reverse_zone_info = std::mem::take(&mut zone.reverse_zone);
+        let subdomains = std::mem::take(&mut zone.subdomains);
+        let pools = std::mem::take(&mut zone.pools);
+        let mut zone_context = context::ZoneContext::new(conf_context, zone)?;
+
+        if new_zones.has_zone(zone_context.get_name_ref()) {
+            warn!("duplicate zone name encountered. name: \"{}\"", zone_context.get_name_ref());
+            continue;
+        }
+
+        allocator.add_pools(Some(zone_context.get_name_ref()), pools)?;
+
+        if let Some(mut info) = reverse_zone_info {
+            let fill_info = std::mem::take(&mut info.fill);
+            let prefix = reverse_zone_prefix(info)?;
 
-                for record in records {
-                    parse_record(&mut new_zones, &conf_context, &zone_context, &subdomain_contex, record)?;
+            if let Some(other) = new_zones.overlapping_reverse_zone(&prefix) {
+                return Err(error::RuntimeError::ConfigError(format!(
+                    "reverse zone {} ({}) overlaps reverse zone {}", zone_context.get_name_ref(), prefix, other
+                )));
+            }
+
+            check_reverse_boundary(zone_context.get_name_ref(), &prefix)?;
+
+            if let Some(fill_info) = fill_info {
+                reverse_fills.push(fill::ReverseFill::new(
+                    zone_context.get_name(),
+                    prefix,
+                    zone_context.get_ttl(),
+                    fill_info,
+                    vec!(zone_context.get_keys_ref().clone(), conf_context.get_keys_ref().clone())
+                )?);
+            }
+
+            match prefix {
+                net::IpAddrCidr::V4(addr_cidr) => {
+                    zone_context.set_domain(dns::ipv4_reverse_prefix(&addr_cidr, true)?);
+
+                    new_zones.add_v4_rev_zone(
+                        dns::zone::Zone::new(
+                            zone_context.get_name(),
+                            zone_context.get_domain()
+                        ),
+                        addr_cidr
+                    );
+                },
+                net::IpAddrCidr::V6(addr_cidr) => {
+                    zone_context.set_domain(dns::ipv6_reverse_prefix(&addr_cidr, true)?);
+
+                    new_zones.add_v6_rev_zone(
+                        dns::zone::Zone::new(
+                            zone_context.get_name(),
+                            zone_context.get_domain()
+                        ),
+                        addr_cidr
+                    );
                 }
             }
+        } else {
+            new_zones.add_zone(dns::zone::Zone::new(
+                zone_context.get_name(),
+                zone_context.get_domain()
+            ));
+        }
+
+        // the soa and ns records go first, before any record another zone
+        // adds to this one
+        for record in soa::zone_records(conf_context, &zone_context)? {
+            new_zones.add_record_to(zone_context.get_name_ref(), record);
         }
 
-        for (_, zone) in new_zones.into_inner() {
-            println!("handling zone: {}", zone.get_name());
-            let mut path = std::path::PathBuf::new();
-            path.push(conf_context.get_directory_ref().as_str());
-            path.push(zone.get_name_ref().as_str());
-
-            let mut tmp_path = std::path::PathBuf::new();
-            tmp_path.push("/tmp");
-            tmp_path.push(zone.get_name_ref().as_str());
-            let mut tmp_file = std::fs::File::create(tmp_path.as_path())?;
-
-            write!(tmp_file, "; ------------------------------------------------------------------------------\n")?;
-            write!(tmp_file, "; zone file generated from dns-zones-builder\n")?;
-            write!(tmp_file, "; {}\n", path.display())?;
-            write!(tmp_file, "{}\n", zone)?;
-
-            let cmd = std::process::Command::new("named-checkzone")
-                .arg(zone.get_origin_ref().as_str())
-                .arg(tmp_path.as_path())
-                .output()?;
-
-            if cmd.status.code().unwrap() != 0 {
-                std::io::stdout().write_all(&cmd.stdout).unwrap();
-                std::io::stderr().write_all(&cmd.stderr).unwrap();
-                std::fs::remove_file(tmp_path.as_path())?;
+        if zone_context.get_idn_comments() {
+            if let Some(unicode) = zone_context.get_unicode_domain_ref() {
+                new_zones.add_record_to(
+                    zone_context.get_name_ref(),
+                    dns::record::Record::Comment(format!("origin {}", unicode))
+                );
+            }
+        }
+
+        pre_builts.push((zone_context.get_name(), zone_context, subdomains));
+    }
+
+    for (name, zone_context, subdomains) in pre_builts {
+        let mut first = new_zones.get_zones_ref()[&name].get_records_ref().is_empty();
+        zone_hooks.insert(name.clone(), zone_context.get_hooks_ref().clone());
+        new_zones.set_current(name);
+
+        for subdomain in subdomains {
+            if !first {
+                new_zones.add_record(dns::record::Record::Blank);
             } else {
-                std::io::stdout().write_all(&cmd.stdout).unwrap();
-                std::fs::rename(tmp_path.as_path(), path.as_path())?;
+                first = false;
+            }
+
+            for (index, mut subdomain) in range::expand(subdomain)? {
+                let records = std::mem::take(&mut subdomain.records);
+                let mut subdomain_contex = context::SubDomainContext::new(conf_context, &zone_context, subdomain)?;
+                subdomain_contex.set_index(index);
+
+                if zone_context.get_idn_comments() {
+                    if let Some(unicode) = subdomain_contex.get_unicode_domain_ref() {
+                        new_zones.add_record(dns::record::Record::Comment(unicode.clone()));
+                    }
+                }
+
+                for entry in records {
+                    parse_record(new_zones, allocator, conf_context, &zone_context, &subdomain_contex, entry.record)?;
+                }
             }
         }
     }
 
-    Ok(0)
+    Ok((reverse_fills, zone_hooks))
+}
+
+/// runs the hooks of a zone that was written and adds their results to the
+/// report of the zone. returns an error when a hook fails after putting the
+/// previous zone file back if the hook asks for it
+fn run_hooks(
+    zone: &dns::zone::Zone,
+    path: &std::path::Path,
+    hooks: &[config::Hook],
+    previous: Option<&[u8]>,
+    zone_report: &mut report::ZoneReport
+) -> error::Result<()> {
+    zone_report.hooks = hooks::run(hooks, zone.get_name_ref(), zone.get_origin_ref(), path)?;
+
+    let failed = match zone_report.hooks.last() {
+        Some(last) if !last.success => &hooks[zone_report.hooks.len() - 1],
+        _ => return Ok(())
+    };
+
+    if failed.on_failure == Some(config::HookFailure::Rollback) {
+        deploy::restore(path, previous)?;
+
+        warn!("rolled back zone file {}", path.display());
+
+        zone_report.status = report::ZoneStatus::Failed;
+    }
+
+    Err(error::RuntimeError::Error(format!(
+        "hook \"{}\" failed for zone \"{}\"", failed.command, zone.get_name_ref()
+    )))
+}
+
+/// the lock file storing pool allocations lives next to the config file.
+/// every view has its own lock file
+fn lock_file_path(file: &std::path::Path, view: Option<&String>) -> std::path::PathBuf {
+    let mut name = file.file_name().unwrap_or_default().to_os_string();
+
+    if let Some(view) = view {
+        name.push(".");
+        name.push(view);
+    }
+
+    name.push(".lock");
+
+    file.with_file_name(name)
 }
 
 fn load_file(file: std::path::PathBuf) -> error::Result<config::Config> {
     if let Some(ext) = file.extension() {
         if ext.eq("yaml") || ext.eq("yml") {
-            Ok(serde_yaml::from_reader::<
-                std::io::BufReader<std::fs::File>,
-                config::Config
-            >(std::io::BufReader::new(
-                std::fs::File::open(&file)?
-            ))?)
+            let contents = std::fs::read_to_string(&file)?;
+
+            serde_yaml::from_str::<config::Config>(&contents).map_err(|err| {
+                let location = err.location().map(|l| (l.line(), l.column()));
+
+                explain_load_error(serde_yaml::from_str(&contents).ok(), location)
+                    .unwrap_or_else(|| err.into())
+            })
         } else if ext.eq("json") {
-            Ok(serde_json::from_reader::<
-                std::io::BufReader<std::fs::File>,
-                config::Config
-            >(std::io::BufReader::new(
-                std::fs::File::open(&file)?
-            ))?)
+            let contents = std::fs::read_to_string(&file)?;
+
+            serde_json::from_str::<config::Config>(&contents).map_err(|err| {
+                let location = Some((err.line(), err.column()));
+
+                explain_load_error(serde_json::from_str(&contents).ok(), location)
+                    .unwrap_or_else(|| err.into())
+            })
         } else {
             Err(error::RuntimeError::InvalidFileExtension(ext.to_os_string()))
         }
@@ -245,6 +746,85 @@ fn load_file(file: std::path::PathBuf) -> error::Result<config::Config> {
     }
 }
 
+/// turns a config that failed to load into an error naming the zone,
+/// subdomain or record that is wrong. the config is given as a generic value
+fn explain_load_error(
+    value: Option<serde_json::Value>,
+    location: Option<(usize, usize)>
+) -> Option<error::RuntimeError> {
+    let msg = schema::explain(&value?)?;
+
+    Some(error::RuntimeError::ConfigError(match location {
+        Some((line, column)) => format!("{} ({}:{})", msg, line, column),
+        None => msg
+    }))
+}
+
+fn reverse_zone_prefix(info: config::ReverseZone) -> error::Result<net::IpAddrCidr> {
+    let prefix = match (info.prefix, info.addr, info.cidr) {
+        (Some(prefix), None, None) => prefix,
+        (None, Some(addr), Some(cidr)) => match info.r#type {
+            Some(config::ReverseType::V4) => net::IpAddrCidr::V4(net::Ipv4AddrCidr::from_addr(
+                addr.parse().map_err(|_| net::error::Error::InvalidV4Addr(addr))?, cidr
+            )?),
+            Some(config::ReverseType::V6) => net::IpAddrCidr::V6(net::Ipv6AddrCidr::from_addr(
+                addr.parse().map_err(|_| net::error::Error::InvalidV6Addr(addr))?, cidr
+            )?),
+            None => return Err(error::RuntimeError::ConfigError(
+                "reverse zone given as addr and cidr requires a type".to_owned()
+            ))
+        },
+        _ => return Err(error::RuntimeError::ConfigError(
+            "reverse zone requires either a prefix or an addr and cidr".to_owned()
+        ))
+    };
+
+    match (&info.r#type, &prefix) {
+        (Some(config::ReverseType::V4), net::IpAddrCidr::V6(_)) |
+        (Some(config::ReverseType::V6), net::IpAddrCidr::V4(_)) => Err(error::RuntimeError::ConfigError(
+            format!("reverse zone type does not match the given prefix: {}", prefix)
+        )),
+        _ => Ok(prefix)
+    }
+}
+
+/// reverse zone origins can only end on an octet (ipv4) or nibble (ipv6)
+/// boundary. warns about prefixes that do not, since their origin covers more
+/// addresses than the prefix
+fn check_reverse_boundary(name: &str, prefix: &net::IpAddrCidr) -> error::Result<()> {
+    let (step, boundary, cidr) = match prefix {
+        net::IpAddrCidr::V4(v4) => (8, "an octet", *v4.cidr_ref()),
+        net::IpAddrCidr::V6(v6) => (4, "a nibble", *v6.cidr_ref())
+    };
+
+    if cidr % step == 0 {
+        return Ok(());
+    }
+
+    if cidr < step {
+        return Err(error::RuntimeError::ConfigError(format!(
+            "reverse zone {} ({}) must have a prefix of at least /{}", name, prefix, step
+        )));
+    }
+
+    let aligned = (cidr / step + 1) * step;
+    let mut subnets: Vec<String> = match prefix {
+        net::IpAddrCidr::V4(v4) => v4.subnets(aligned)?.map(|subnet| subnet.to_string()).take(5).collect(),
+        net::IpAddrCidr::V6(v6) => v6.subnets(aligned)?.map(|subnet| subnet.to_string()).take(5).collect()
+    };
+
+    if subnets.len() > 4 {
+        subnets[4] = "...".to_owned();
+    }
+
+    warn!(
+        "reverse zone {} ({}) does not end on {} boundary and its origin covers more addresses. consider splitting it into {}",
+        name, prefix, boundary, subnets.join(", ")
+    );
+
+    Ok(())
+}
+
 fn get_reverse(reverse: config::ReverseValue) -> bool {
     match reverse {
         config::ReverseValue::Bool(rtn) => rtn,
@@ -254,12 +834,17 @@ fn get_reverse(reverse: config::ReverseValue) -> bool {
 
 fn parse_record(
     dns_zone: &mut dns::ZoneStorage,
+    allocator: &mut ipam::Allocator,
     config: &context::ConfigContext,
     zone: &context::ZoneContext,
     subdomain: &context::SubDomainContext,
     record: config::Record
 ) -> error::Result<()> {
-    match record {
+    let scope = template::Scope::new(vec!(
+        subdomain.get_keys_ref(), zone.get_keys_ref(), config.get_keys_ref()
+    ));
+
+    match scope.render_record(record)? {
         config::Record::Soa {
             domain, email, 
             serial, refresh, retry, expire, nct
@@ -267,8 +852,8 @@ fn parse_record(
             dns_zone.add_record(dns::record::Record::Soa {
                 name: subdomain.get_domain(),
                 ttl: zone.get_ttl(),
-                domain: zone.with_domain(domain), 
-                email: zone.with_domain(email),
+                domain: zone.with_domain(domain)?,
+                email: soa::rname(zone, email)?,
                 serial, refresh, 
                 retry, expire, nct
             });
@@ -277,7 +862,7 @@ fn parse_record(
             dns_zone.add_record(dns::record::Record::Ns {
                 name: subdomain.get_domain(),
                 ttl: zone.get_ttl(),
-                domain: zone.with_domain(domain)
+                domain: zone.with_domain(domain)?
             });
         },
         config::Record::A {address, reverse} => {
@@ -289,11 +874,11 @@ fn parse_record(
 
             match address {
                 config::Ipv4Address::Single(single) => {
-                    parse_ipv4_type(dns_zone, config, zone, subdomain, single, reverse)?;
+                    parse_ipv4_type(dns_zone, allocator, zone, subdomain, single, reverse)?;
                 },
                 config::Ipv4Address::Multiple(multiple) => {
                     for addr in multiple {
-                        parse_ipv4_type(dns_zone, config, zone, subdomain, addr, reverse)?;
+                        parse_ipv4_type(dns_zone, allocator, zone, subdomain, addr, reverse)?;
                     }
                 }
             };
@@ -307,11 +892,11 @@ fn parse_record(
 
             match address {
                 config::Ipv6Address::Single(single) => {
-                    parse_ipv6_type(dns_zone, config, zone, subdomain, single, reverse)?;
+                    parse_ipv6_type(dns_zone, allocator, zone, subdomain, single, reverse)?;
                 },
                 config::Ipv6Address::Multiple(multiple) => {
                     for addr in multiple {
-                        parse_ipv6_type(dns_zone, config, zone, subdomain, addr, reverse)?;
+                        parse_ipv6_type(dns_zone, allocator, zone, subdomain, addr, reverse)?;
                     }
                 }
             };
@@ -321,34 +906,97 @@ fn parse_record(
                 name: subdomain.get_domain(),
                 ttl: zone.get_ttl(),
                 priority,
-                domain: zone.with_domain(domain)
+                domain: zone.with_domain(domain)?
             });
         },
         config::Record::Cname {alias} => {
             dns_zone.add_record(dns::record::Record::Cname {
                 name: subdomain.get_domain(),
                 ttl: zone.get_ttl(),
-                alias: zone.with_domain(alias)
+                alias: zone.with_domain(alias)?
             });
         },
         config::Record::Txt {value} => {
             dns_zone.add_record(dns::record::Record::Txt {
                 name: subdomain.get_domain(),
                 ttl: zone.get_ttl(),
-                value
+                value: match value {
+                    config::TxtValue::Single(single) => dns::record::TxtData::new(single),
+                    config::TxtValue::Multiple(multiple) => dns::record::TxtData::from_strings(multiple)
+                }
             });
         },
         config::Record::Ptr {address} => {
             match address {
                 config::PtrAddress::Single(single) => {
-                    parse_ptr_value(dns_zone, config, zone, subdomain, single)?;
+                    parse_ptr_value(dns_zone, zone, subdomain, single)?;
                 },
                 config::PtrAddress::Multiple(multiple) => {
                     for addr in multiple {
-                        parse_ptr_value(dns_zone, config, zone, subdomain, addr)?;
+                        parse_ptr_value(dns_zone, zone, subdomain, addr)?;
                     }
                 }
             };
+        },
+        config::Record::Spf(detail) => {
+            dns_zone.add_record(dns::record::Record::Txt {
+                name: subdomain.get_domain(),
+                ttl: zone.get_ttl(),
+                value: dns::record::TxtData::new(mail::spf_string(detail)?)
+            });
+        },
+        config::Record::Dkim(detail) => {
+            dns_zone.add_record(dns::record::Record::Txt {
+                name: mail::dkim_domain(&detail.selector, &subdomain.get_domain())?,
+                ttl: zone.get_ttl(),
+                value: dns::record::TxtData::new(mail::dkim_string(config, &detail)?)
+            });
+        },
+        config::Record::Dmarc(detail) => {
+            dns_zone.add_record(dns::record::Record::Txt {
+                name: mail::dmarc_domain(&subdomain.get_domain()),
+                ttl: zone.get_ttl(),
+                value: dns::record::TxtData::new(mail::dmarc_string(detail)?)
+            });
+        },
+        config::Record::Tlsa(detail) => {
+            dns_zone.add_record(dns::record::Record::Tlsa {
+                name: fingerprint::tlsa_domain(detail.port, &detail.protocol, &subdomain.get_domain()),
+                ttl: zone.get_ttl(),
+                usage: fingerprint::tlsa_usage(detail.usage.as_ref().unwrap_or(&config::TlsaUsage::DaneEe)),
+                selector: fingerprint::tlsa_selector(detail.selector.as_ref().unwrap_or(&config::TlsaSelector::Spki)),
+                matching: fingerprint::tlsa_matching(detail.matching.as_ref().unwrap_or(&config::TlsaMatching::Sha256)),
+                data: fingerprint::tlsa_data(config, &detail)?
+            });
+        },
+        config::Record::Svcb(detail) => {
+            let (priority, target, params) = parse_svcb_detail(zone, subdomain, detail)?;
+
+            dns_zone.add_record(dns::record::Record::Svcb {
+                name: subdomain.get_domain(),
+                ttl: zone.get_ttl(),
+                priority, target, params
+            });
+        },
+        config::Record::Https(detail) => {
+            let (priority, target, params) = parse_svcb_detail(zone, subdomain, detail)?;
+
+            dns_zone.add_record(dns::record::Record::Https {
+                name: subdomain.get_domain(),
+                ttl: zone.get_ttl(),
+                priority, target, params
+            });
+        },
+        config::Record::Sshfp(detail) => {
+            let (algorithm, fingerprint_type, fingerprint) = fingerprint::sshfp_data(config, &detail)?;
+
+            dns_zone.add_record(dns::record::Record::Sshfp {
+                name: subdomain.get_domain(),
+                ttl: zone.get_ttl(),
+                algorithm,
+                fingerprint_type,
+                fingerprint
+            });
         }
     }
 
@@ -357,7 +1005,7 @@ fn parse_record(
 
 fn parse_ipv4_type(
     dns_zone: &mut dns::ZoneStorage,
-    config: &context::ConfigContext,
+    allocator: &mut ipam::Allocator,
     zone: &context::ZoneContext,
     subdomain: &context::SubDomainContext,
     ipv4_type: config::Ipv4Type,
@@ -365,25 +1013,28 @@ fn parse_ipv4_type(
 ) -> error::Result<()> {
     let ip = match ipv4_type {
         config::Ipv4Type::Ip(ip) => ip,
-        config::Ipv4Type::Str(string) => get_ipv4_from_string(config, zone, string)?,
+        config::Ipv4Type::Str(string) => get_ipv4_from_string(subdomain, string)?,
         config::Ipv4Type::Detail(detail) => {
             reverse = detail.reverse.unwrap_or(reverse);
 
             match detail.ip {
                 config::Ipv4Value::Ip(i) => i,
-                config::Ipv4Value::Str(string) => get_ipv4_from_string(config, zone, string)?
+                config::Ipv4Value::Str(string) => get_ipv4_from_string(subdomain, string)?
             }
+        },
+        config::Ipv4Type::Pool(pool_ref) => {
+            return allocator.request(
+                dns_zone, zone, subdomain, &pool_ref.pool, true, pool_ref.reverse.unwrap_or(reverse)
+            );
         }
     };
 
     if reverse {
-        if !dns_zone.add_v4_reverse_record(&ip, dns::record::Record::Ptr {
+        dns_zone.add_v4_reverse_record(&ip, dns::record::Record::Ptr {
             name: dns::ipv4_reverse_string(&ip, true)?,
             ttl: zone.get_ttl(),
             domain: subdomain.get_domain()
-        }) {
-            println!("failed to find reverse zone for ip address: {}", ip);
-        }
+        });
     }
 
     dns_zone.add_record(dns::record::Record::A {
@@ -395,49 +1046,63 @@ fn parse_ipv4_type(
     Ok(())
 }
 
-fn parse_keyed_string(
-    config: &context::ConfigContext,
+fn parse_svcb_detail(
     zone: &context::ZoneContext,
-    string: String
-) -> error::Result<String> {
-    let mut working = string.clone();
+    subdomain: &context::SubDomainContext,
+    detail: config::SvcbDetail
+) -> error::Result<(u16, String, dns::record::SvcParams)> {
+    let target = if detail.target.eq(".") {
+        detail.target
+    } else {
+        zone.with_domain(detail.target)?
+    };
+    let mut params = dns::record::SvcParams::default();
 
-    for capture in KEY_REGEX.captures_iter(string.as_str()) {
-        let key = &capture[1].to_string();
+    if let Some(given) = detail.params {
+        params.mandatory = given.mandatory.unwrap_or_default();
+        params.alpn = given.alpn.unwrap_or_default();
+        params.no_default_alpn = given.no_default_alpn.unwrap_or(false);
+        params.port = given.port;
+        params.ech = given.ech;
 
-        if let Some(value) = zone.find_key(&key) {
-            working = working.replace(&capture[0], value.as_str());
-        } else if let Some(value) = config.find_key(&key) {
-            working = working.replace(&capture[0], value.as_str());
-        } else {
-            return Err(error::RuntimeError::Error(
-                format!("failed to find requested key: {}", key)
-            ));
+        for hint in given.ipv4hint.unwrap_or_default() {
+            params.ipv4hint.push(match hint {
+                config::Ipv4Value::Ip(ip) => ip,
+                config::Ipv4Value::Str(string) => get_ipv4_from_string(subdomain, string)?
+            });
+        }
+
+        for hint in given.ipv6hint.unwrap_or_default() {
+            params.ipv6hint.push(match hint {
+                config::Ipv6Value::Ip(ip) => ip,
+                config::Ipv6Value::Str(string) => get_ipv6_from_string(subdomain, string)?
+            });
         }
     }
 
-    Ok(working)
+    params.validate(detail.priority).map_err(|msg| error::RuntimeError::ConfigError(
+        format!("invalid service parameters for target \"{}\". {}", target, msg)
+    ))?;
+
+    Ok((detail.priority, target, params))
 }
 
 fn get_ipv4_from_string(
-    config: &context::ConfigContext,
-    zone: &context::ZoneContext,
+    subdomain: &context::SubDomainContext,
     string: String
 ) -> error::Result<std::net::Ipv4Addr> {
-    let working = parse_keyed_string(config, zone, string)?;
-
-    if let Ok(ip) = working.parse::<std::net::Ipv4Addr>() {
+    if let Some(std::net::IpAddr::V4(ip)) = range::address_offset(&string, subdomain.get_index())? {
         Ok(ip)
     } else {
-        Err(error::RuntimeError::Error(
-            format!("invalid ipv4 string given: {}", working)
+        Err(error::RuntimeError::ConfigError(
+            format!("invalid ipv4 string given: {}", string)
         ))
     }
 }
 
 fn parse_ipv6_type(
     dns_zone: &mut dns::ZoneStorage,
-    config: &context::ConfigContext,
+    allocator: &mut ipam::Allocator,
     zone: &context::ZoneContext,
     subdomain: &context::SubDomainContext,
     ipv6_type: config::Ipv6Type,
@@ -445,25 +1110,28 @@ fn parse_ipv6_type(
 ) -> error::Result<()> {
     let ip = match ipv6_type {
         config::Ipv6Type::Ip(ip) => ip,
-        config::Ipv6Type::Str(string) => get_ipv6_from_string(config, zone, string)?,
+        config::Ipv6Type::Str(string) => get_ipv6_from_string(subdomain, string)?,
         config::Ipv6Type::Detail(detail) => {
             reverse = detail.reverse.unwrap_or(reverse);
 
             match detail.ip {
                 config::Ipv6Value::Ip(ip) => ip,
-                config::Ipv6Value::Str(string) => get_ipv6_from_string(config, zone, string)?
+                config::Ipv6Value::Str(string) => get_ipv6_from_string(subdomain, string)?
             }
+        },
+        config::Ipv6Type::Pool(pool_ref) => {
+            return allocator.request(
+                dns_zone, zone, subdomain, &pool_ref.pool, false, pool_ref.reverse.unwrap_or(reverse)
+            );
         }
     };
 
     if reverse {
-        if !dns_zone.add_v6_reverse_record(&ip, dns::record::Record::Ptr {
+        dns_zone.add_v6_reverse_record(&ip, dns::record::Record::Ptr {
             name: dns::ipv6_reverse_string(&ip, true)?,
             ttl: zone.get_ttl(),
             domain: subdomain.get_domain()
-        }) {
-            println!("failed to find reverse zone for ip address: {}", ip);
-        }
+        });
     }
 
     dns_zone.add_record(dns::record::Record::Aaaa {
@@ -476,31 +1144,27 @@ fn parse_ipv6_type(
 }
 
 fn get_ipv6_from_string(
-    config: &context::ConfigContext,
-    zone: &context::ZoneContext,
+    subdomain: &context::SubDomainContext,
     string: String
 ) -> error::Result<std::net::Ipv6Addr> {
-    let working = parse_keyed_string(config, zone, string)?;
-
-    if let Ok(ip) = working.parse::<std::net::Ipv6Addr>() {
+    if let Some(std::net::IpAddr::V6(ip)) = range::address_offset(&string, subdomain.get_index())? {
         Ok(ip)
     } else {
-        Err(error::RuntimeError::Error(
-            format!("invalid ipv6 string given: {}", working)
+        Err(error::RuntimeError::ConfigError(
+            format!("invalid ipv6 string given: {}", string)
         ))
     }
 }
 
 fn parse_ptr_value(
     dns_zone: &mut dns::ZoneStorage,
-    config: &context::ConfigContext,
     zone: &context::ZoneContext,
     subdomain: &context::SubDomainContext,
     ptr_value: config::PtrValue,
 ) -> error::Result<()> {
     let ip = match ptr_value {
         config::PtrValue::Ip(ip) => ip,
-        config::PtrValue::Str(string) => get_ip_from_string(config, zone, string)?
+        config::PtrValue::Str(string) => get_ip_from_string(subdomain, string)?
     };
 
     dns_zone.add_record(dns::record::Record::Ptr {
@@ -513,19 +1177,69 @@ fn parse_ptr_value(
 }
 
 fn get_ip_from_string(
-    config: &context::ConfigContext,
-    zone: &context::ZoneContext,
+    subdomain: &context::SubDomainContext,
     string: String
 ) -> error::Result<std::net::IpAddr> {
-    let working = parse_keyed_string(config, zone, string)?;
-
-    if let Ok(ip) = working.parse::<std::net::Ipv4Addr>() {
-        Ok(std::net::IpAddr::V4(ip))
-    } else if let Ok(ip) = working.parse::<std::net::Ipv6Addr>() {
-        Ok(std::net::IpAddr::V6(ip))
+    if let Some(ip) = range::address_offset(&string, subdomain.get_index())? {
+        Ok(ip)
     } else {
-        Err(error::RuntimeError::Error(
-            format!("invalid ipv4/ipv6 string given: {}", working)
+        Err(error::RuntimeError::ConfigError(
+            format!("invalid ipv4/ipv6 string given: {}", string)
         ))
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// adds the zones of a yaml config to a new storage
+    fn add(config: &str) -> error::Result<dns::ZoneStorage> {
+        let mut conf: config::Config = serde_yaml::from_str(config).unwrap();
+        let zones = std::mem::take(&mut conf.zones);
+        let file = std::path::Path::new("/etc/zones/config.yaml");
+        let mut allocator = ipam::Allocator::new(lock_file_path(file, None), false)?;
+        let conf_context = context::ConfigContext::new(conf, file);
+        let mut storage = dns::ZoneStorage::new();
+
+        add_zones(&conf_context, zones, &mut allocator, &mut storage)?;
+
+        Ok(storage)
+    }
+
+    #[test]
+    fn idn_comments() {
+        let storage = add("
+name: test
+idn_comments: true
+zones:
+  - name: café
+    subdomains:
+      - name: bücher
+        records: [{type: A, address: 192.0.2.1}]
+      - name: www
+        records: [{type: A, address: 192.0.2.2}]
+").unwrap();
+        let zone = storage.get_zones_ref()["café"].to_string();
+
+        assert!(zone.contains("; origin café.\n"));
+        assert!(zone.contains("; bücher.café.\nxn--bcher-kva.xn--caf-dma.\t"));
+        assert!(!zone.contains("; www"));
+    }
+
+    #[test]
+    fn invalid_labels() {
+        let result = add("
+name: test
+zones:
+  - name: example
+    subdomains:
+      - name: a\u{200d}é
+        records: [{type: A, address: 192.0.2.1}]
+");
+
+        assert!(matches!(
+            result, Err(error::RuntimeError::ConfigError(msg)) if msg.contains("invalid domain label")
+        ));
+    }
+}
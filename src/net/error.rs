@@ -1,9 +1,14 @@
 use std::{fmt};
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Error {
     InvalidV4Cidr(u8),
-    InvalidV6Cidr(u8)
+    InvalidV6Cidr(u8),
+    InvalidPrefix(String),
+    InvalidV4Addr(String),
+    InvalidV6Addr(String),
+    HostBitsSet(String),
+    InvalidSubnetCidr(u8, u8)
 }
 
 impl Error {
@@ -13,7 +18,17 @@ impl Error {
             Error::InvalidV4Cidr(cidr) =>
                 format!("given cidr is invalid for Ipv4 address. must be between 1 and 32 given: {}", cidr),
             Error::InvalidV6Cidr(cidr) =>
-                format!("given cidr is invalid for Ipv6 address. must be between 1 and 128 given: {}", cidr)
+                format!("given cidr is invalid for Ipv6 address. must be between 1 and 128 given: {}", cidr),
+            Error::InvalidPrefix(given) =>
+                format!("given prefix is invalid. expected <address>/<cidr> given: {}", given),
+            Error::InvalidV4Addr(given) =>
+                format!("given address is not a valid Ipv4 address. given: {}", given),
+            Error::InvalidV6Addr(given) =>
+                format!("given address is not a valid Ipv6 address. given: {}", given),
+            Error::HostBitsSet(given) =>
+                format!("given prefix has host bits set. given: {}", given),
+            Error::InvalidSubnetCidr(cidr, new_cidr) =>
+                format!("given subnet cidr must be between {} and the max cidr for the address. given: {}", cidr, new_cidr)
         }
     }
 
@@ -28,4 +43,6 @@ impl fmt::Display for Error {
 
 }
 
-pub type Result<T> = std::result::Result<T, Error>;
\ No newline at end of file
+impl std::error::Error for Error {}
+
+pub type Result<T> = std::result::Result<T, Error>;
@@ -1,5 +1,8 @@
 use std::{fmt};
-use std::net::{Ipv4Addr, Ipv6Addr};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::str::{FromStr};
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
 pub mod error;
 
@@ -13,6 +16,28 @@ pub fn ipv6_to_u128(addr: &Ipv6Addr) -> u128 {
     u128::from_be_bytes(addr.octets())
 }
 
+/// converts either address type into a single number. ipv4 addresses occupy
+/// the lower 32 bits
+#[inline]
+pub fn ip_to_u128(addr: &IpAddr) -> u128 {
+    match addr {
+        IpAddr::V4(v4) => ipv4_to_u32(v4) as u128,
+        IpAddr::V6(v6) => ipv6_to_u128(v6)
+    }
+}
+
+/// splits a "<address>/<cidr>" string into its parts
+fn split_prefix(given: &str) -> error::Result<(&str, u8)> {
+    if let Some((addr, cidr)) = given.split_once('/') {
+        if let Ok(cidr) = cidr.parse::<u8>() {
+            return Ok((addr, cidr));
+        }
+    }
+
+    Err(error::Error::InvalidPrefix(given.to_owned()))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub struct Ipv4AddrCidr {
     addr: Ipv4Addr,
     cidr: u8
@@ -43,6 +68,7 @@ impl Ipv4AddrCidr {
         }
     }
 
+    #[allow(clippy::unused_unit)]
     pub fn set_addr(&mut self, addr: Ipv4Addr) -> () {
         self.addr = addr;
     }
@@ -51,16 +77,17 @@ impl Ipv4AddrCidr {
         &self.addr
     }
 
+    #[allow(dead_code, clippy::clone_on_copy)]
     pub fn addr_clone(&self) -> Ipv4Addr {
         self.addr.clone()
     }
 
     pub fn set_cidr(&mut self, cidr: u8) -> bool {
         if Ipv4AddrCidr::check_cidr(&cidr) {
+            false
+        } else {
             self.cidr = cidr;
             true
-        } else {
-            false
         }
     }
 
@@ -68,16 +95,17 @@ impl Ipv4AddrCidr {
         &self.cidr
     }
 
+    #[allow(dead_code)]
     pub fn cidr_clone(&self) -> u8 {
         self.cidr
     }
 
     pub fn cidr_mask(&self) -> u32 {
-        u32::MAX >> self.cidr
+        u32::MAX.checked_shr(self.cidr as u32).unwrap_or(0)
     }
 
     pub fn available_addresses(&self) -> u32 {
-        (u32::MAX >> self.cidr) + 1
+        self.cidr_mask().wrapping_add(1)
     }
 
     pub fn as_u32(&self) -> u32 {
@@ -85,7 +113,7 @@ impl Ipv4AddrCidr {
     }
 
     pub fn start_u32(&self) -> u32 {
-        self.as_u32() & !(u32::MAX >> self.cidr)
+        self.as_u32() & !self.cidr_mask()
     }
 
     pub fn start(&self) -> Ipv4Addr {
@@ -93,7 +121,7 @@ impl Ipv4AddrCidr {
     }
 
     pub fn finish_u32(&self) -> u32 {
-        self.as_u32() | (u32::MAX >> self.cidr)
+        self.as_u32() | self.cidr_mask()
     }
 
     pub fn finish(&self) -> Ipv4Addr {
@@ -102,11 +130,117 @@ impl Ipv4AddrCidr {
 
     pub fn in_range(&self, check: &Ipv4Addr) -> bool {
         let check_value = ipv4_to_u32(check);
-        let value = self.as_u32();
 
-        // check_value >= start && check_value <= finish
-        check_value >= (value & !(u32::MAX >> self.cidr)) && 
-        check_value <= (value | (u32::MAX >> self.cidr))
+        check_value >= self.start_u32() && check_value <= self.finish_u32()
+    }
+
+    /// returns the address at the given offset from the start of the prefix
+    /// or None if the offset is outside of the prefix
+    pub fn nth(&self, offset: u128) -> Option<Ipv4Addr> {
+        if offset > self.cidr_mask() as u128 {
+            None
+        } else {
+            Some(Ipv4Addr::from(self.start_u32() + offset as u32))
+        }
+    }
+
+    /// iterates over every address of the prefix
+    pub fn addresses(&self) -> Ipv4AddrIter {
+        Ipv4AddrIter {
+            next: Some(self.start_u32()),
+            finish: self.finish_u32()
+        }
+    }
+
+    /// iterates over the host addresses of the prefix. the network and broadcast
+    /// addresses are skipped for prefixes shorter than /31
+    pub fn hosts(&self) -> Ipv4AddrIter {
+        if self.cidr >= 31 {
+            self.addresses()
+        } else {
+            Ipv4AddrIter {
+                next: Some(self.start_u32() + 1),
+                finish: self.finish_u32() - 1
+            }
+        }
+    }
+
+    /// splits the prefix into subnets with the given cidr
+    pub fn subnets(&self, new_cidr: u8) -> error::Result<Ipv4AddrCidrSubnetIter> {
+        if new_cidr < self.cidr || Ipv4AddrCidr::check_cidr(&new_cidr) {
+            Err(error::Error::InvalidSubnetCidr(self.cidr, new_cidr))
+        } else {
+            Ok(Ipv4AddrCidrSubnetIter {
+                next: Some(self.start_u32()),
+                finish: self.finish_u32(),
+                cidr: new_cidr
+            })
+        }
+    }
+
+    /// returns the prefix that is one bit shorter and contains this prefix
+    pub fn supernet(&self) -> Option<Ipv4AddrCidr> {
+        let mut rtn = *self;
+
+        if rtn.set_cidr(self.cidr.checked_sub(1)?) {
+            rtn.set_addr(rtn.start());
+            Some(rtn)
+        } else {
+            None
+        }
+    }
+
+    /// checks if every address of the other prefix is inside of this prefix
+    pub fn contains(&self, other: &Ipv4AddrCidr) -> bool {
+        other.cidr >= self.cidr && self.in_range(&other.start())
+    }
+
+    /// checks if the two prefixes share any addresses
+    pub fn overlaps(&self, other: &Ipv4AddrCidr) -> bool {
+        self.start_u32() <= other.finish_u32() && other.start_u32() <= self.finish_u32()
+    }
+
+    /// merges the given prefixes into the smallest list of prefixes that
+    /// covers the same addresses. contained prefixes are dropped and
+    /// adjacent prefixes of the same size are joined
+    pub fn aggregate(prefixes: &[Ipv4AddrCidr]) -> Vec<Ipv4AddrCidr> {
+        let mut working: Vec<Ipv4AddrCidr> = prefixes.iter()
+            .map(|p| Ipv4AddrCidr { addr: p.start(), cidr: p.cidr })
+            .collect();
+        working.sort();
+
+        loop {
+            let mut changed = false;
+            let mut rtn: Vec<Ipv4AddrCidr> = Vec::with_capacity(working.len());
+
+            for prefix in working {
+                if let Some(last) = rtn.last_mut() {
+                    if last.contains(&prefix) {
+                        changed = true;
+                        continue;
+                    }
+
+                    if last.cidr == prefix.cidr {
+                        if let Some(supernet) = last.supernet() {
+                            if supernet.start_u32() == last.start_u32() &&
+                               supernet.finish_u32() == prefix.finish_u32() {
+                                *last = supernet;
+                                changed = true;
+                                continue;
+                            }
+                        }
+                    }
+                }
+
+                rtn.push(prefix);
+            }
+
+            if !changed {
+                return rtn;
+            }
+
+            working = rtn;
+        }
     }
 
     pub fn prefix(&self) -> String {
@@ -119,9 +253,93 @@ impl fmt::Display for Ipv4AddrCidr {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "{}/{}", self.addr, self.cidr)
     }
-    
+
+}
+
+impl FromStr for Ipv4AddrCidr {
+    type Err = error::Error;
+
+    /// parses "10.0.0.0/8". the address must not have any host bits set
+    fn from_str(given: &str) -> error::Result<Ipv4AddrCidr> {
+        let (addr, cidr) = split_prefix(given)?;
+        let addr = addr.parse::<Ipv4Addr>()
+            .map_err(|_| error::Error::InvalidV4Addr(addr.to_owned()))?;
+        let rtn = Ipv4AddrCidr::from_addr(addr, cidr)?;
+
+        if rtn.as_u32() != rtn.start_u32() {
+            Err(error::Error::HostBitsSet(given.to_owned()))
+        } else {
+            Ok(rtn)
+        }
+    }
 }
 
+impl Serialize for Ipv4AddrCidr {
+
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+
+}
+
+impl<'de> Deserialize<'de> for Ipv4AddrCidr {
+
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?.parse().map_err(serde::de::Error::custom)
+    }
+
+}
+
+/// iterator over the addresses of an Ipv4AddrCidr
+pub struct Ipv4AddrIter {
+    next: Option<u32>,
+    finish: u32
+}
+
+impl Iterator for Ipv4AddrIter {
+    type Item = Ipv4Addr;
+
+    fn next(&mut self) -> Option<Ipv4Addr> {
+        let current = self.next?;
+
+        self.next = if current < self.finish {
+            Some(current + 1)
+        } else {
+            None
+        };
+
+        Some(Ipv4Addr::from(current))
+    }
+}
+
+/// iterator over the subnets of an Ipv4AddrCidr
+pub struct Ipv4AddrCidrSubnetIter {
+    next: Option<u32>,
+    finish: u32,
+    cidr: u8
+}
+
+impl Iterator for Ipv4AddrCidrSubnetIter {
+    type Item = Ipv4AddrCidr;
+
+    fn next(&mut self) -> Option<Ipv4AddrCidr> {
+        let current = self.next?;
+        let last = current | u32::MAX.checked_shr(self.cidr as u32).unwrap_or(0);
+
+        self.next = if last < self.finish {
+            Some(last + 1)
+        } else {
+            None
+        };
+
+        Some(Ipv4AddrCidr {
+            addr: Ipv4Addr::from(current),
+            cidr: self.cidr
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub struct Ipv6AddrCidr {
     addr: Ipv6Addr,
     cidr: u8
@@ -132,7 +350,8 @@ impl Ipv6AddrCidr {
     pub fn check_cidr(cidr: &u8) -> bool {
         *cidr == 0 || *cidr > 128
     }
-    
+
+    #[allow(clippy::too_many_arguments)]
     pub fn new(a: u16, b: u16, c: u16, d: u16, e: u16, f: u16, g: u16, h: u16, cidr: u8) -> error::Result<Ipv6AddrCidr> {
         if Ipv6AddrCidr::check_cidr(&cidr) {
             Err(error::Error::InvalidV6Cidr(cidr))
@@ -156,16 +375,17 @@ impl Ipv6AddrCidr {
         &self.addr
     }
 
+    #[allow(dead_code, clippy::clone_on_copy)]
     pub fn addr_clone(&self) -> Ipv6Addr {
         self.addr.clone()
     }
 
     pub fn set_cidr(&mut self, cidr: u8) -> bool {
         if Ipv6AddrCidr::check_cidr(&cidr) {
+            false
+        } else {
             self.cidr = cidr;
             true
-        } else {
-            false
         }
     }
 
@@ -173,16 +393,17 @@ impl Ipv6AddrCidr {
         &self.cidr
     }
 
+    #[allow(dead_code)]
     pub fn cidr_clone(&self) -> u8 {
         self.cidr
     }
 
     pub fn cidr_mask(&self) -> u128 {
-        u128::MAX >> self.cidr
+        u128::MAX.checked_shr(self.cidr as u32).unwrap_or(0)
     }
 
     pub fn available_addresses(&self) -> u128 {
-        (u128::MAX >> self.cidr) + 1
+        self.cidr_mask().wrapping_add(1)
     }
 
     pub fn as_u128(&self) -> u128 {
@@ -190,7 +411,7 @@ impl Ipv6AddrCidr {
     }
 
     pub fn start_u128(&self) -> u128 {
-        self.as_u128() & !(u128::MAX >> self.cidr)
+        self.as_u128() & !self.cidr_mask()
     }
 
     pub fn start(&self) -> Ipv6Addr {
@@ -198,7 +419,7 @@ impl Ipv6AddrCidr {
     }
 
     pub fn finish_u128(&self) -> u128 {
-        self.as_u128() | (u128::MAX >> self.cidr)
+        self.as_u128() | self.cidr_mask()
     }
 
     pub fn finish(&self) -> Ipv6Addr {
@@ -207,11 +428,116 @@ impl Ipv6AddrCidr {
 
     pub fn in_range(&self, check: &Ipv6Addr) -> bool {
         let check_value = ipv6_to_u128(check);
-        let value = self.as_u128();
 
-        // check_value >= start && check_value <= finish
-        check_value >= (value & !(u128::MAX >> self.cidr)) &&
-        check_value <= (value | (u128::MAX >> self.cidr))
+        check_value >= self.start_u128() && check_value <= self.finish_u128()
+    }
+
+    /// returns the address at the given offset from the start of the prefix
+    /// or None if the offset is outside of the prefix
+    pub fn nth(&self, offset: u128) -> Option<Ipv6Addr> {
+        if offset > self.cidr_mask() {
+            None
+        } else {
+            Some(Ipv6Addr::from(self.start_u128() + offset))
+        }
+    }
+
+    /// iterates over every address of the prefix
+    pub fn addresses(&self) -> Ipv6AddrIter {
+        Ipv6AddrIter {
+            next: Some(self.start_u128()),
+            finish: self.finish_u128()
+        }
+    }
+
+    /// iterates over the host addresses of the prefix. the subnet-router anycast
+    /// address is skipped for prefixes shorter than /127
+    pub fn hosts(&self) -> Ipv6AddrIter {
+        if self.cidr >= 127 {
+            self.addresses()
+        } else {
+            Ipv6AddrIter {
+                next: Some(self.start_u128() + 1),
+                finish: self.finish_u128()
+            }
+        }
+    }
+
+    /// splits the prefix into subnets with the given cidr
+    pub fn subnets(&self, new_cidr: u8) -> error::Result<Ipv6AddrCidrSubnetIter> {
+        if new_cidr < self.cidr || Ipv6AddrCidr::check_cidr(&new_cidr) {
+            Err(error::Error::InvalidSubnetCidr(self.cidr, new_cidr))
+        } else {
+            Ok(Ipv6AddrCidrSubnetIter {
+                next: Some(self.start_u128()),
+                finish: self.finish_u128(),
+                cidr: new_cidr
+            })
+        }
+    }
+
+    /// returns the prefix that is one bit shorter and contains this prefix
+    pub fn supernet(&self) -> Option<Ipv6AddrCidr> {
+        let mut rtn = *self;
+
+        if rtn.set_cidr(self.cidr.checked_sub(1)?) {
+            Some(Ipv6AddrCidr { addr: rtn.start(), ..rtn })
+        } else {
+            None
+        }
+    }
+
+    /// checks if every address of the other prefix is inside of this prefix
+    pub fn contains(&self, other: &Ipv6AddrCidr) -> bool {
+        other.cidr >= self.cidr && self.in_range(&other.start())
+    }
+
+    /// checks if the two prefixes share any addresses
+    pub fn overlaps(&self, other: &Ipv6AddrCidr) -> bool {
+        self.start_u128() <= other.finish_u128() && other.start_u128() <= self.finish_u128()
+    }
+
+    /// merges the given prefixes into the smallest list of prefixes that
+    /// covers the same addresses. contained prefixes are dropped and
+    /// adjacent prefixes of the same size are joined
+    pub fn aggregate(prefixes: &[Ipv6AddrCidr]) -> Vec<Ipv6AddrCidr> {
+        let mut working: Vec<Ipv6AddrCidr> = prefixes.iter()
+            .map(|p| Ipv6AddrCidr { addr: p.start(), cidr: p.cidr })
+            .collect();
+        working.sort();
+
+        loop {
+            let mut changed = false;
+            let mut rtn: Vec<Ipv6AddrCidr> = Vec::with_capacity(working.len());
+
+            for prefix in working {
+                if let Some(last) = rtn.last_mut() {
+                    if last.contains(&prefix) {
+                        changed = true;
+                        continue;
+                    }
+
+                    if last.cidr == prefix.cidr {
+                        if let Some(supernet) = last.supernet() {
+                            if supernet.start_u128() == last.start_u128() &&
+                               supernet.finish_u128() == prefix.finish_u128() {
+                                *last = supernet;
+                                changed = true;
+                                continue;
+                            }
+                        }
+                    }
+                }
+
+                rtn.push(prefix);
+            }
+
+            if !changed {
+                return rtn;
+            }
+
+            working = rtn;
+        }
     }
 
     pub fn prefix(&self) -> String {
@@ -224,5 +550,354 @@ impl fmt::Display for Ipv6AddrCidr {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "{}/{}", self.addr, self.cidr)
     }
-    
-}
\ No newline at end of file
+
+}
+
+impl FromStr for Ipv6AddrCidr {
+    type Err = error::Error;
+
+    /// parses "2001:db8::/32". the address must not have any host bits set
+    fn from_str(given: &str) -> error::Result<Ipv6AddrCidr> {
+        let (addr, cidr) = split_prefix(given)?;
+        let addr = addr.parse::<Ipv6Addr>()
+            .map_err(|_| error::Error::InvalidV6Addr(addr.to_owned()))?;
+        let rtn = Ipv6AddrCidr::from_addr(addr, cidr)?;
+
+        if rtn.as_u128() != rtn.start_u128() {
+            Err(error::Error::HostBitsSet(given.to_owned()))
+        } else {
+            Ok(rtn)
+        }
+    }
+}
+
+impl Serialize for Ipv6AddrCidr {
+
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+
+}
+
+impl<'de> Deserialize<'de> for Ipv6AddrCidr {
+
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?.parse().map_err(serde::de::Error::custom)
+    }
+
+}
+
+/// iterator over the addresses of an Ipv6AddrCidr
+pub struct Ipv6AddrIter {
+    next: Option<u128>,
+    finish: u128
+}
+
+impl Iterator for Ipv6AddrIter {
+    type Item = Ipv6Addr;
+
+    fn next(&mut self) -> Option<Ipv6Addr> {
+        let current = self.next?;
+
+        self.next = if current < self.finish {
+            Some(current + 1)
+        } else {
+            None
+        };
+
+        Some(Ipv6Addr::from(current))
+    }
+}
+
+/// iterator over the subnets of an Ipv6AddrCidr
+pub struct Ipv6AddrCidrSubnetIter {
+    next: Option<u128>,
+    finish: u128,
+    cidr: u8
+}
+
+impl Iterator for Ipv6AddrCidrSubnetIter {
+    type Item = Ipv6AddrCidr;
+
+    fn next(&mut self) -> Option<Ipv6AddrCidr> {
+        let current = self.next?;
+        let last = current | u128::MAX.checked_shr(self.cidr as u32).unwrap_or(0);
+
+        self.next = if last < self.finish {
+            Some(last + 1)
+        } else {
+            None
+        };
+
+        Some(Ipv6AddrCidr {
+            addr: Ipv6Addr::from(current),
+            cidr: self.cidr
+        })
+    }
+}
+
+/// either an Ipv4 or Ipv6 prefix
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum IpAddrCidr {
+    V4(Ipv4AddrCidr),
+    V6(Ipv6AddrCidr)
+}
+
+impl IpAddrCidr {
+
+    pub fn start(&self) -> IpAddr {
+        match self {
+            IpAddrCidr::V4(cidr) => IpAddr::V4(cidr.start()),
+            IpAddrCidr::V6(cidr) => IpAddr::V6(cidr.start())
+        }
+    }
+
+    pub fn finish(&self) -> IpAddr {
+        match self {
+            IpAddrCidr::V4(cidr) => IpAddr::V4(cidr.finish()),
+            IpAddrCidr::V6(cidr) => IpAddr::V6(cidr.finish())
+        }
+    }
+
+    pub fn contains(&self, other: &IpAddrCidr) -> bool {
+        match (self, other) {
+            (IpAddrCidr::V4(cidr), IpAddrCidr::V4(other)) => cidr.contains(other),
+            (IpAddrCidr::V6(cidr), IpAddrCidr::V6(other)) => cidr.contains(other),
+            _ => false
+        }
+    }
+
+    pub fn in_range(&self, check: &IpAddr) -> bool {
+        match (self, check) {
+            (IpAddrCidr::V4(cidr), IpAddr::V4(addr)) => cidr.in_range(addr),
+            (IpAddrCidr::V6(cidr), IpAddr::V6(addr)) => cidr.in_range(addr),
+            _ => false
+        }
+    }
+
+    pub fn nth(&self, offset: u128) -> Option<IpAddr> {
+        match self {
+            IpAddrCidr::V4(cidr) => cidr.nth(offset).map(IpAddr::V4),
+            IpAddrCidr::V6(cidr) => cidr.nth(offset).map(IpAddr::V6)
+        }
+    }
+}
+
+impl fmt::Display for IpAddrCidr {
+
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            IpAddrCidr::V4(v4) => write!(f, "{}", v4),
+            IpAddrCidr::V6(v6) => write!(f, "{}", v6)
+        }
+    }
+
+}
+
+impl FromStr for IpAddrCidr {
+    type Err = error::Error;
+
+    fn from_str(given: &str) -> error::Result<IpAddrCidr> {
+        if given.contains(':') {
+            Ok(IpAddrCidr::V6(given.parse()?))
+        } else {
+            Ok(IpAddrCidr::V4(given.parse()?))
+        }
+    }
+}
+
+impl Serialize for IpAddrCidr {
+
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+
+}
+
+impl<'de> Deserialize<'de> for IpAddrCidr {
+
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?.parse().map_err(serde::de::Error::custom)
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn v4(given: &str) -> Ipv4AddrCidr {
+        given.parse().unwrap()
+    }
+
+    fn v6(given: &str) -> Ipv6AddrCidr {
+        given.parse().unwrap()
+    }
+
+    #[test]
+    fn zero_cidr_is_rejected() {
+        assert_eq!(Ipv4AddrCidr::new(0, 0, 0, 0, 0), Err(error::Error::InvalidV4Cidr(0)));
+        assert!("0.0.0.0/0".parse::<Ipv4AddrCidr>().is_err());
+        assert!("::/0".parse::<Ipv6AddrCidr>().is_err());
+        assert_eq!(v4("0.0.0.0/1").supernet(), None);
+        assert_eq!(v6("::/1").supernet(), None);
+    }
+
+    #[test]
+    fn set_cidr() {
+        let mut prefix = v4("10.0.0.0/24");
+        assert!(prefix.set_cidr(16));
+        assert_eq!(prefix, v4("10.0.0.0/16"));
+        assert!(prefix.set_cidr(32));
+        assert_eq!(prefix, v4("10.0.0.0/32"));
+        assert!(!prefix.set_cidr(0));
+        assert!(!prefix.set_cidr(33));
+        assert_eq!(prefix, v4("10.0.0.0/32"));
+
+        let mut prefix = v6("2001:db8::/48");
+        assert!(prefix.set_cidr(64));
+        assert_eq!(prefix, v6("2001:db8::/64"));
+        assert!(prefix.set_cidr(128));
+        assert_eq!(prefix, v6("2001:db8::/128"));
+        assert!(!prefix.set_cidr(0));
+        assert!(!prefix.set_cidr(129));
+        assert_eq!(prefix, v6("2001:db8::/128"));
+    }
+
+    #[test]
+    fn single_address_prefixes() {
+        let host = v4("10.0.0.5/32");
+        assert_eq!(host.start(), host.finish());
+        assert_eq!(host.available_addresses(), 1);
+        assert_eq!(host.hosts().collect::<Vec<_>>(), vec![Ipv4Addr::new(10, 0, 0, 5)]);
+        assert_eq!(host.supernet(), Some(v4("10.0.0.4/31")));
+        assert!(host.subnets(32).unwrap().eq(vec![host]));
+
+        let host = v6("2001:db8::1/128");
+        assert_eq!(host.start(), host.finish());
+        assert_eq!(host.available_addresses(), 1);
+        assert_eq!(host.hosts().collect::<Vec<_>>(), vec!["2001:db8::1".parse::<Ipv6Addr>().unwrap()]);
+        assert_eq!(host.supernet(), Some(v6("2001:db8::/127")));
+
+        let last = v4("255.255.255.255/32");
+        assert_eq!(last.addresses().count(), 1);
+        assert_eq!(last.subnets(32).unwrap().count(), 1);
+
+        let last = v6("ffff:ffff:ffff:ffff:ffff:ffff:ffff:ffff/128");
+        assert_eq!(last.addresses().count(), 1);
+        assert_eq!(last.subnets(128).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn hosts() {
+        let hosts: Vec<Ipv4Addr> = v4("192.0.2.0/24").hosts().collect();
+        assert_eq!(hosts.len(), 254);
+        assert_eq!(hosts.first(), Some(&Ipv4Addr::new(192, 0, 2, 1)));
+        assert_eq!(hosts.last(), Some(&Ipv4Addr::new(192, 0, 2, 254)));
+
+        assert_eq!(v4("192.0.2.0/31").hosts().count(), 2);
+        assert_eq!(v4("255.255.255.0/24").hosts().last(), Some(Ipv4Addr::new(255, 255, 255, 254)));
+
+        let hosts: Vec<Ipv6Addr> = v6("2001:db8::/126").hosts().collect();
+        assert_eq!(hosts.len(), 3);
+        assert_eq!(hosts.first(), Some(&"2001:db8::1".parse::<Ipv6Addr>().unwrap()));
+        assert_eq!(v6("2001:db8::/127").hosts().count(), 2);
+    }
+
+    #[test]
+    fn subnets() {
+        let subnets: Vec<Ipv4AddrCidr> = v4("10.0.0.0/24").subnets(26).unwrap().collect();
+        assert_eq!(subnets, vec![
+            v4("10.0.0.0/26"), v4("10.0.0.64/26"), v4("10.0.0.128/26"), v4("10.0.0.192/26")
+        ]);
+
+        assert!(v4("10.0.0.0/24").subnets(24).unwrap().eq(vec![v4("10.0.0.0/24")]));
+        assert_eq!(v4("255.255.255.0/24").subnets(25).unwrap().count(), 2);
+        assert!(v4("10.0.0.0/24").subnets(23).is_err());
+        assert!(v4("10.0.0.0/24").subnets(33).is_err());
+
+        let subnets: Vec<Ipv6AddrCidr> = v6("2001:db8::/32").subnets(34).unwrap().collect();
+        assert_eq!(subnets, vec![
+            v6("2001:db8::/34"), v6("2001:db8:4000::/34"), v6("2001:db8:8000::/34"), v6("2001:db8:c000::/34")
+        ]);
+        assert_eq!(v6("ffff:ffff:ffff:ffff:ffff:ffff:ffff:fffe/127").subnets(128).unwrap().count(), 2);
+        assert!(v6("2001:db8::/32").subnets(129).is_err());
+    }
+
+    #[test]
+    fn supernet_of_unaligned_address() {
+        let prefix = Ipv4AddrCidr::from_addr(Ipv4Addr::new(10, 0, 1, 7), 24).unwrap();
+        assert_eq!(prefix.supernet(), Some(v4("10.0.0.0/23")));
+
+        let prefix = Ipv6AddrCidr::from_addr("2001:db8:0:1::5".parse().unwrap(), 64).unwrap();
+        assert_eq!(prefix.supernet(), Some(v6("2001:db8::/63")));
+    }
+
+    #[test]
+    fn contains_and_overlaps() {
+        let outer = v4("10.0.0.0/8");
+        let inner = v4("10.1.0.0/16");
+        assert!(outer.contains(&inner));
+        assert!(!inner.contains(&outer));
+        assert!(outer.contains(&outer));
+        assert!(outer.overlaps(&inner) && inner.overlaps(&outer));
+
+        assert!(!v4("10.0.0.0/24").overlaps(&v4("10.0.1.0/24")));
+        assert!(!v4("10.0.0.0/24").contains(&v4("10.0.1.0/24")));
+        assert!(v4("128.0.0.0/1").contains(&v4("255.255.255.255/32")));
+        assert!(!v4("0.0.0.0/1").overlaps(&v4("128.0.0.0/1")));
+
+        let outer = v6("2001:db8::/32");
+        assert!(outer.contains(&v6("2001:db8:1::/48")));
+        assert!(outer.contains(&v6("2001:db8::1/128")));
+        assert!(!outer.contains(&v6("2001:db9::/48")));
+        assert!(!outer.overlaps(&v6("2001:db9::/32")));
+        assert!(outer.overlaps(&v6("2001::/16")));
+
+        let unaligned = Ipv4AddrCidr::from_addr(Ipv4Addr::new(10, 0, 0, 77), 24).unwrap();
+        assert!(unaligned.contains(&v4("10.0.0.0/25")));
+        assert!(v4("10.0.0.0/24").contains(&unaligned));
+    }
+
+    #[test]
+    fn aggregate_adjacent() {
+        assert_eq!(
+            Ipv4AddrCidr::aggregate(&[v4("10.0.0.128/25"), v4("10.0.0.0/25")]),
+            vec![v4("10.0.0.0/24")]
+        );
+        assert_eq!(
+            Ipv4AddrCidr::aggregate(&[v4("10.0.0.0/26"), v4("10.0.0.64/26"), v4("10.0.0.128/26"), v4("10.0.0.192/26")]),
+            vec![v4("10.0.0.0/24")]
+        );
+        assert_eq!(
+            Ipv4AddrCidr::aggregate(&[v4("0.0.0.0/1"), v4("128.0.0.0/1")]),
+            vec![v4("0.0.0.0/1"), v4("128.0.0.0/1")]
+        );
+        assert_eq!(
+            Ipv6AddrCidr::aggregate(&[v6("2001:db8::/128"), v6("2001:db8::1/128"), v6("2001:db8::2/127")]),
+            vec![v6("2001:db8::/126")]
+        );
+    }
+
+    #[test]
+    fn aggregate_unaligned() {
+        // adjacent but not inside of the same /23
+        assert_eq!(
+            Ipv4AddrCidr::aggregate(&[v4("10.0.1.0/24"), v4("10.0.2.0/24")]),
+            vec![v4("10.0.1.0/24"), v4("10.0.2.0/24")]
+        );
+
+        // contained prefixes are dropped and host bits are ignored
+        let unaligned = Ipv4AddrCidr::from_addr(Ipv4Addr::new(10, 0, 0, 200), 25).unwrap();
+        assert_eq!(
+            Ipv4AddrCidr::aggregate(&[v4("10.0.0.0/25"), unaligned, v4("10.0.0.5/32")]),
+            vec![v4("10.0.0.0/24")]
+        );
+
+        assert_eq!(
+            Ipv6AddrCidr::aggregate(&[v6("2001:db9::/48"), v6("2001:db9::/32"), v6("2001:dba::/32")]),
+            vec![v6("2001:db9::/32"), v6("2001:dba::/32")]
+        );
+        assert_eq!(Ipv4AddrCidr::aggregate(&[]), vec![]);
+    }
+}
@@ -0,0 +1,179 @@
+use log::{warn};
+
+use crate::config;
+use crate::error;
+use crate::extend;
+
+/// removes a subdomain, or only the records of the given type, from a zone
+fn remove_records(zone: &mut config::Zone, remove: config::ProfileRemove, profile: &str) -> error::Result<()> {
+    let index = zone.subdomains.iter()
+        .position(|s| s.name == remove.subdomain)
+        .ok_or_else(|| error::RuntimeError::ConfigError(format!(
+            "profile \"{}\" removes unknown subdomain \"{}\" of zone \"{}\"", profile, remove.subdomain, zone.name
+        )))?;
+
+    match remove.r#type {
+        Some(given) => {
+            let records = &mut zone.subdomains[index].records;
+            let before = records.len();
+
+            records.retain(|entry| !entry.record.get_type().eq_ignore_ascii_case(&given));
+
+            if records.len() == before {
+                return Err(error::RuntimeError::ConfigError(format!(
+                    "profile \"{}\" removes {} records from subdomain \"{}\" of zone \"{}\" but none exist",
+                    profile, given, remove.subdomain, zone.name
+                )));
+            }
+        },
+        None => {
+            zone.subdomains.remove(index);
+        }
+    }
+
+    Ok(())
+}
+
+/// overlays the selected profile on top of the config and its zones. keys
+/// and nameservers are merged, ttls replaced, records removed and then added
+pub fn apply(
+    config: &mut config::Config,
+    zones: &mut [config::Zone],
+    profile: &str
+) -> error::Result<()> {
+    let selected = config.profiles.as_mut()
+        .and_then(|profiles| profiles.remove(profile))
+        .ok_or_else(|| error::RuntimeError::ConfigError(format!("unknown profile requested: \"{}\"", profile)))?;
+
+    let mut warnings = Vec::new();
+
+    extend::merge_keys(&mut config.keys, selected.keys);
+
+    if selected.nameservers.is_some() {
+        config.nameservers = selected.nameservers;
+    }
+
+    if let Some(ttl) = selected.ttl {
+        for zone in zones.iter_mut() {
+            zone.ttl = Some(ttl);
+        }
+    }
+
+    for (name, overlay) in selected.zones.unwrap_or_default() {
+        let zone = zones.iter_mut().find(|z| z.name == name).ok_or_else(|| error::RuntimeError::ConfigError(
+            format!("profile \"{}\" references unknown zone \"{}\"", profile, name)
+        ))?;
+
+        extend::merge_keys(&mut zone.keys, overlay.keys);
+
+        if overlay.ttl.is_some() {
+            zone.ttl = overlay.ttl;
+        }
+
+        if overlay.nameservers.is_some() {
+            zone.nameservers = overlay.nameservers;
+        }
+
+        for remove in overlay.remove.unwrap_or_default() {
+            remove_records(zone, remove, profile)?;
+        }
+
+        extend::merge_subdomains(
+            &mut zone.subdomains,
+            overlay.subdomains,
+            &format!("profile \"{}\"", profile),
+            &format!("zone \"{}\"", name),
+            &mut warnings
+        );
+    }
+
+    for warning in warnings {
+        warn!("{}", warning);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = "
+name: test
+keys: {env: production, region: east}
+zones:
+  - name: example
+    ttl: 3600
+    keys: {host: www}
+    subdomains:
+      - name: www
+        records:
+          - {type: A, address: 10.0.0.1}
+          - {type: Aaaa, address: '2001:db8::1'}
+      - name: mail
+        records: [{type: A, address: 10.0.0.2}]
+  - name: other
+    ttl: 3600
+profiles:
+  staging:
+    ttl: 60
+    keys: {env: staging}
+    zones:
+      example:
+        ttl: 30
+        keys: {host: stage}
+        remove:
+          - {subdomain: www, type: AAAA}
+          - {subdomain: mail}
+        subdomains:
+          - name: test
+            records: [{type: A, address: 10.0.1.1}]
+";
+
+    fn applied(config: &str, profile: &str) -> error::Result<(config::Config, Vec<config::Zone>)> {
+        let mut config: config::Config = serde_yaml::from_str(config).unwrap();
+        let mut zones = std::mem::take(&mut config.zones);
+
+        apply(&mut config, &mut zones, profile)?;
+
+        Ok((config, zones))
+    }
+
+    #[test]
+    fn overlay() {
+        let (config, zones) = applied(CONFIG, "staging").unwrap();
+        let config_keys = config.keys.unwrap();
+
+        assert_eq!((config_keys["env"].as_str(), config_keys["region"].as_str()), ("staging", "east"));
+        assert_eq!(zones[0].keys.as_ref().unwrap()["host"], "stage");
+        assert_eq!((zones[0].ttl, zones[1].ttl), (Some(30), Some(60)));
+
+        let subdomains = &zones[0].subdomains;
+        assert_eq!(subdomains.iter().map(|s| s.name.as_str()).collect::<Vec<_>>(), vec!("www", "test"));
+        assert_eq!(subdomains[0].records.iter().map(|r| r.record.get_type()).collect::<Vec<_>>(), vec!("A"));
+    }
+
+    #[test]
+    fn removal_types() {
+        for given in ["AAAA", "aaaa", "Aaaa"] {
+            let config = CONFIG.replace("type: AAAA}", &format!("type: {}}}", given));
+            let (_, zones) = applied(&config, "staging").unwrap();
+
+            assert_eq!(zones[0].subdomains[0].records.len(), 1);
+        }
+
+        let missing = applied(&CONFIG.replace("type: AAAA}", "type: MX}"), "staging");
+        assert!(missing.err().unwrap().get_msg().contains("removes MX records from subdomain \"www\" of zone \"example\" but none exist"));
+    }
+
+    #[test]
+    fn unknown_names() {
+        assert!(applied(CONFIG, "production").err().unwrap().get_msg().contains("unknown profile requested: \"production\""));
+
+        let zone = applied(&CONFIG.replace("      example:\n", "      missing:\n"), "staging");
+        assert!(zone.err().unwrap().get_msg().contains("profile \"staging\" references unknown zone \"missing\""));
+
+        let subdomain = applied(&CONFIG.replace("{subdomain: mail}", "{subdomain: ftp}"), "staging");
+        assert!(subdomain.err().unwrap().get_msg().contains("profile \"staging\" removes unknown subdomain \"ftp\" of zone \"example\""));
+    }
+}
@@ -0,0 +1,198 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use lazy_static::lazy_static;
+use regex::{Regex};
+
+use crate::config;
+use crate::error;
+use crate::net;
+
+/// max number of subdomains a single range is allowed to generate
+const RANGE_LIMIT: u32 = 65536;
+
+lazy_static! {
+    static ref INDEX_REGEX: Regex = Regex::new(r"\{n(?::(?:0([0-9]{1,2}))?(x)?)?}").unwrap();
+}
+
+/// replaces every "{n}" in the format with the given index. "{n:03}" pads
+/// the index with zeros and "{n:x}" writes it as hex
+fn format_index(format: &str, index: u32) -> String {
+    INDEX_REGEX.replace_all(format, |capture: &regex::Captures| {
+        let width = capture.get(1).map(|w| w.as_str().parse::<usize>().unwrap()).unwrap_or(0);
+
+        if capture.get(2).is_some() {
+            format!("{:0>width$x}", index, width = width)
+        } else {
+            format!("{:0>width$}", index, width = width)
+        }
+    }).into_owned()
+}
+
+/// expands a subdomain with a range into one subdomain per index. the
+/// generated subdomains are returned with the index they were created for
+pub fn expand(mut subdomain: config::SubDomain) -> error::Result<Vec<(Option<u32>, config::SubDomain)>> {
+    let range = match subdomain.range.take() {
+        Some(range) => range,
+        None => return Ok(vec!((None, subdomain)))
+    };
+
+    if range.from > range.to {
+        return Err(error::RuntimeError::ConfigError(format!(
+            "invalid range for subdomain \"{}\". from ({}) is greater than to ({})",
+            subdomain.name, range.from, range.to
+        )));
+    }
+
+    if range.to - range.from >= RANGE_LIMIT {
+        return Err(error::RuntimeError::ConfigError(format!(
+            "range for subdomain \"{}\" generates more than {} subdomains", subdomain.name, RANGE_LIMIT
+        )));
+    }
+
+    if !INDEX_REGEX.is_match(&range.format) {
+        return Err(error::RuntimeError::ConfigError(format!(
+            "range format \"{}\" for subdomain \"{}\" does not contain {{n}}", range.format, subdomain.name
+        )));
+    }
+
+    let mut rtn = Vec::with_capacity((range.to - range.from) as usize + 1);
+
+    for index in range.from..=range.to {
+        let mut generated = subdomain.clone();
+        generated.domain = Some(format_index(&range.format, index));
+
+        rtn.push((Some(index), generated));
+    }
+
+    Ok(rtn)
+}
+
+enum AddressBase {
+    Addr(IpAddr),
+    Prefix(net::IpAddrCidr)
+}
+
+/// parses the start of an address expression. this is either an address or
+/// a prefix that may be followed by an ipv6 interface identifier, e.g.
+/// "2001:db8:1::/48::10". the identifier is returned as an offset into the
+/// prefix
+fn address_base(given: &str) -> error::Result<Option<(AddressBase, u128)>> {
+    let (addr, rest) = match given.split_once('/') {
+        Some(split) => split,
+        None => return Ok(given.parse::<IpAddr>().ok().map(|ip| (AddressBase::Addr(ip), 0)))
+    };
+    let split = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+    let (cidr, suffix) = rest.split_at(split);
+
+    if addr.parse::<IpAddr>().is_err() || cidr.parse::<u8>().is_err() {
+        return Ok(None);
+    }
+
+    let prefix = format!("{}/{}", addr, cidr).parse::<net::IpAddrCidr>()?;
+    let offset = if suffix.is_empty() {
+        0
+    } else {
+        match (&prefix, suffix.parse::<Ipv6Addr>()) {
+            (net::IpAddrCidr::V6(_), Ok(id)) if suffix.starts_with("::") => net::ipv6_to_u128(&id),
+            _ => return Err(error::RuntimeError::ConfigError(format!(
+                "invalid suffix \"{}\" for prefix {}", suffix, prefix
+            )))
+        }
+    };
+
+    Ok(Some((AddressBase::Prefix(prefix), offset)))
+}
+
+/// parses an address expression. the expression starts with an address or
+/// prefix optionally followed by offsets that are added to it, e.g.
+/// "10.0.0.10 + n + 2" or "10.40.0.0/16 + 42". "n" is the index of the
+/// subdomain range and is only available for generated subdomains. results
+/// of a prefix must be inside of the prefix. returns None if the given string
+/// does not start with an address or prefix
+pub fn address_offset(given: &str, index: Option<u32>) -> error::Result<Option<IpAddr>> {
+    let mut terms = given.split('+');
+    let (base, mut offset) = match address_base(terms.next().unwrap_or_default().trim())? {
+        Some(base) => base,
+        None => return Ok(None)
+    };
+
+    for term in terms {
+        let term = term.trim();
+        let value = if term == "n" {
+            index.ok_or_else(|| error::RuntimeError::ConfigError(format!(
+                "address \"{}\" uses n outside of a subdomain range", given
+            )))? as u128
+        } else {
+            term.parse::<u128>().map_err(|_| error::RuntimeError::ConfigError(format!(
+                "invalid address offset \"{}\" in \"{}\"", term, given
+            )))?
+        };
+
+        offset = offset.checked_add(value).ok_or_else(|| error::RuntimeError::ConfigError(format!(
+            "address offset in \"{}\" is too large", given
+        )))?;
+    }
+
+    let out_of_range = || error::RuntimeError::ConfigError(format!("address \"{}\" is out of range", given));
+
+    Ok(Some(match base {
+        AddressBase::Addr(IpAddr::V4(v4)) => {
+            let value = (net::ipv4_to_u32(&v4) as u128).checked_add(offset)
+                .filter(|v| *v <= u32::MAX as u128)
+                .ok_or_else(out_of_range)?;
+
+            IpAddr::V4(Ipv4Addr::from(value as u32))
+        },
+        AddressBase::Addr(IpAddr::V6(v6)) => {
+            let value = net::ipv6_to_u128(&v6).checked_add(offset)
+                .ok_or_else(out_of_range)?;
+
+            IpAddr::V6(Ipv6Addr::from(value))
+        },
+        AddressBase::Prefix(prefix) => prefix.nth(offset).ok_or_else(|| error::RuntimeError::ConfigError(format!(
+            "address \"{}\" is outside of prefix {}", given, prefix
+        )))?
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(given: &str) -> Option<IpAddr> {
+        Some(given.parse().unwrap())
+    }
+
+    #[test]
+    fn index_format() {
+        assert_eq!(format_index("node{n}", 7), "node7");
+        assert_eq!(format_index("node{n:03}-{n:x}", 10), "node010-a");
+        assert_eq!(format_index("rack{n:02x}", 255), "rackff");
+    }
+
+    #[test]
+    fn addresses() {
+        assert_eq!(address_offset("10.0.0.10", None).unwrap(), ip("10.0.0.10"));
+        assert_eq!(address_offset("10.0.0.10 + n + 2", Some(5)).unwrap(), ip("10.0.0.17"));
+        assert_eq!(address_offset("10.0.0.255+1", None).unwrap(), ip("10.0.1.0"));
+        assert_eq!(address_offset("2001:db8::ff + 1", None).unwrap(), ip("2001:db8::100"));
+        assert_eq!(address_offset("www.example.", None).unwrap(), None);
+        assert_eq!(address_offset("{base} + 1", None).unwrap(), None);
+
+        assert!(address_offset("10.0.0.10 + n", None).is_err());
+        assert!(address_offset("10.0.0.10 + x", None).is_err());
+        assert!(address_offset("255.255.255.255 + 1", None).is_err());
+    }
+
+    #[test]
+    fn prefixes() {
+        assert_eq!(address_offset("10.40.0.0/16 + 42", None).unwrap(), ip("10.40.0.42"));
+        assert_eq!(address_offset("10.40.0.0/16+256+n", Some(1)).unwrap(), ip("10.40.1.1"));
+        assert_eq!(address_offset("2001:db8:1::/48::10", None).unwrap(), ip("2001:db8:1::10"));
+        assert_eq!(address_offset("2001:db8:1::/48::10 + n", Some(6)).unwrap(), ip("2001:db8:1::16"));
+
+        assert!(address_offset("10.40.0.0/16 + 65536", None).is_err());
+        assert!(address_offset("10.40.0.0/16::10", None).is_err());
+        assert!(address_offset("2001:db8:1::/48:10", None).is_err());
+    }
+}
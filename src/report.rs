@@ -0,0 +1,245 @@
+use std::collections::{BTreeMap};
+use std::io::{Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use lazy_static::lazy_static;
+use serde::{Serialize};
+
+use crate::changes;
+use crate::dns;
+
+lazy_static! {
+    /// warnings logged during the run, collected for the report
+    static ref WARNINGS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+}
+
+/// warnings are only kept once a report asks for them
+static CAPTURE: AtomicBool = AtomicBool::new(false);
+
+/// forwards log records to env_logger and keeps every warning while
+/// capturing so that it can be added to the report, even when warnings are
+/// not printed
+struct Logger {
+    inner: env_logger::Logger
+}
+
+impl log::Log for Logger {
+
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        metadata.level() <= log::Level::Warn || self.inner.enabled(metadata)
+    }
+
+    fn log(&self, record: &log::Record) {
+        if record.level() == log::Level::Warn && CAPTURE.load(Ordering::Relaxed) {
+            if let Ok(mut warnings) = WARNINGS.lock() {
+                warnings.push(record.args().to_string());
+            }
+        }
+
+        if self.inner.matches(record) {
+            self.inner.log(record);
+        }
+    }
+
+    fn flush(&self) {
+        self.inner.flush();
+    }
+}
+
+/// sets up logging to stderr. the level can be changed with RUST_LOG
+pub fn init_logger(level: log::LevelFilter) {
+    let inner = env_logger::Builder::new()
+        .filter_level(level)
+        .parse_default_env()
+        .format(|buf, record| match record.level() {
+            log::Level::Error => writeln!(buf, "error: {}", record.args()),
+            log::Level::Warn => writeln!(buf, "warning: {}", record.args()),
+            _ => writeln!(buf, "{}", record.args())
+        })
+        .build();
+    let max_level = std::cmp::max(inner.filter(), log::LevelFilter::Warn);
+
+    if log::set_boxed_logger(Box::new(Logger { inner })).is_ok() {
+        log::set_max_level(max_level);
+    }
+}
+
+/// keeps the warnings logged from now on for the report
+pub fn capture_warnings() {
+    CAPTURE.store(true, Ordering::Relaxed);
+}
+
+/// takes the warnings logged since capturing started
+pub fn take_warnings() -> Vec<String> {
+    match WARNINGS.lock() {
+        Ok(mut warnings) => std::mem::take(&mut *warnings),
+        Err(_) => Vec::new()
+    }
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ZoneStatus {
+    Unchanged,
+    Written,
+
+    /// validated but not written
+    Checked,
+    Failed
+}
+
+#[derive(Serialize, Debug)]
+pub struct HookReport {
+    pub command: String,
+    pub success: bool,
+    pub output: String
+}
+
+#[derive(Serialize, Debug)]
+pub struct ZoneReport {
+    pub name: String,
+    pub origin: String,
+    pub config: PathBuf,
+    pub view: Option<String>,
+    pub path: PathBuf,
+    pub serial: Option<u32>,
+    pub records: BTreeMap<&'static str, usize>,
+    pub change: changes::Status,
+    pub status: ZoneStatus,
+    pub validation: Vec<String>,
+    pub hooks: Vec<HookReport>,
+
+    /// warnings about the records of the zone. they are listed with the
+    /// warnings of the run as well
+    pub warnings: Vec<String>
+}
+
+impl ZoneReport {
+
+    /// creates the report of a zone. the status is failed until changed
+    pub fn new(
+        config: &Path,
+        view: Option<&str>,
+        zone: &dns::zone::Zone,
+        path: &Path,
+        change: changes::Status
+    ) -> ZoneReport {
+        let mut records = BTreeMap::new();
+
+        for record in zone.get_records_ref() {
+            if let Some(r#type) = record.get_type() {
+                *records.entry(r#type).or_insert(0) += 1;
+            }
+        }
+
+        ZoneReport {
+            name: zone.get_name(),
+            origin: zone.get_origin_ref().clone(),
+            config: config.to_path_buf(),
+            view: view.map(|v| v.to_owned()),
+            path: path.to_path_buf(),
+            serial: zone.get_serial(),
+            records,
+            change,
+            status: ZoneStatus::Failed,
+            validation: Vec::new(),
+            hooks: Vec::new(),
+            warnings: zone.get_warnings_ref().clone()
+        }
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct FileError {
+    pub config: PathBuf,
+    pub error: String
+}
+
+/// summary of a run given with "--report json"
+#[derive(Serialize, Debug, Default)]
+pub struct Report {
+    pub zones: Vec<ZoneReport>,
+
+    /// every warning logged during the run
+    pub warnings: Vec<String>,
+
+    /// the error of every config file that failed to build
+    pub errors: Vec<FileError>
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn zone() -> dns::zone::Zone {
+        let mut zone = dns::zone::Zone::new("example".to_owned(), "example.".to_owned());
+        zone.add_record(dns::record::Record::Soa {
+            name: "example.".to_owned(),
+            ttl: 300,
+            domain: "ns1.example.".to_owned(),
+            email: "hostmaster.example.".to_owned(),
+            serial: 2024013100,
+            refresh: 3600,
+            retry: 600,
+            expire: 86400,
+            nct: 300
+        });
+        zone.add_record(dns::record::Record::Ns {
+            name: "example.".to_owned(),
+            ttl: 300,
+            domain: "ns1.example.".to_owned()
+        });
+        zone.add_record(dns::record::Record::Blank);
+        zone.add_record(dns::record::Record::Comment("hosts".to_owned()));
+
+        for address in [[10, 0, 0, 1], [10, 0, 0, 2]] {
+            zone.add_record(dns::record::Record::A {
+                name: "www.example.".to_owned(),
+                ttl: 300,
+                address: address.into()
+            });
+        }
+
+        zone.add_warning("address 10.0.0.1 is assigned to multiple names".to_owned());
+
+        zone
+    }
+
+    #[test]
+    fn zone_report() {
+        let report = ZoneReport::new(
+            Path::new("/etc/zones/config.yaml"), Some("internal"), &zone(), Path::new("/zones/example"), changes::Status::Updated
+        );
+
+        assert_eq!(report.records.iter().map(|(k, v)| (*k, *v)).collect::<Vec<_>>(), vec!(("A", 2), ("NS", 1), ("SOA", 1)));
+        assert_eq!(report.serial, Some(2024013100));
+        assert_eq!(report.status, ZoneStatus::Failed);
+        assert_eq!(report.warnings, vec!("address 10.0.0.1 is assigned to multiple names"));
+
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["view"], "internal");
+        assert_eq!(json["serial"], 2024013100);
+        assert_eq!(json["change"], "updated");
+        assert_eq!(json["status"], "failed");
+        assert_eq!(json["records"], serde_json::json!({"A": 2, "NS": 1, "SOA": 1}));
+        assert_eq!(json["warnings"][0], "address 10.0.0.1 is assigned to multiple names");
+    }
+
+    #[test]
+    fn zone_status() {
+        let mut report = ZoneReport::new(
+            Path::new("config.yaml"), None, &dns::zone::Zone::new("empty".to_owned(), "empty.".to_owned()),
+            Path::new("empty"), changes::Status::Unchanged
+        );
+        report.status = ZoneStatus::Unchanged;
+
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["serial"], serde_json::Value::Null);
+        assert_eq!(json["records"], serde_json::json!({}));
+        assert_eq!(json["change"], "unchanged");
+        assert_eq!(json["status"], "unchanged");
+        assert_eq!(json["warnings"], serde_json::json!([]));
+    }
+}
@@ -0,0 +1,214 @@
+use serde::de::{DeserializeOwned};
+use serde_json::{Map, Value};
+
+use crate::config;
+use crate::error;
+
+/// the json schema of the config format
+pub fn generate() -> error::Result<String> {
+    Ok(serde_json::to_string_pretty(&schemars::schema_for!(config::Config))?)
+}
+
+fn name_of(value: &Value) -> &str {
+    value.get("name").and_then(Value::as_str).unwrap_or("unnamed")
+}
+
+fn check<T: DeserializeOwned>(value: &Value) -> Result<(), String> {
+    serde_json::from_value::<T>(value.clone())
+        .map(|_| ())
+        .map_err(|err| err.to_string())
+}
+
+/// finds the key of an object that fails to load. every key is loaded on
+/// its own together with the base keys, so the missing fields of the probe
+/// can be told apart from the key that is wrong. the base keys are checked
+/// first, for records that is the type selecting the variant
+fn explain_keys<T: DeserializeOwned>(value: &Value, base: &[&str]) -> Option<String> {
+    let object = value.as_object()?;
+    let mut probe = Map::new();
+
+    for key in base {
+        if let Some(field) = object.get(*key) {
+            probe.insert((*key).to_owned(), field.clone());
+        }
+    }
+
+    if let Err(err) = check::<T>(&Value::Object(probe.clone())) {
+        if !err.starts_with("missing field") {
+            return Some(format!("field \"{}\": {}", base.join("\", \""), err));
+        }
+    }
+
+    for (key, field) in object {
+        if base.contains(&key.as_str()) {
+            continue;
+        }
+
+        let mut probe = probe.clone();
+        probe.insert(key.clone(), field.clone());
+
+        if let Err(err) = check::<T>(&Value::Object(probe)) {
+            if !err.starts_with("missing field") {
+                return Some(format!("field \"{}\": {}", key, err));
+            }
+        }
+    }
+
+    None
+}
+
+fn explain_records(records: &Value, owner: &str) -> Option<String> {
+    for (index, record) in records.as_array()?.iter().enumerate() {
+        if let Err(err) = check::<config::SubDomainRecord>(record) {
+            let r#type = record.get("type").and_then(Value::as_str).unwrap_or("without type");
+            let err = explain_keys::<config::SubDomainRecord>(record, &["type"]).unwrap_or(err);
+
+            return Some(format!("record {} ({}) of {}: {}", index + 1, r#type, owner, err));
+        }
+    }
+
+    None
+}
+
+fn explain_subdomains(subdomains: &Value, owner: &str) -> Option<String> {
+    for subdomain in subdomains.as_array()? {
+        if let Err(err) = check::<config::SubDomain>(subdomain) {
+            let owner = format!("subdomain \"{}\" of {}", name_of(subdomain), owner);
+
+            return Some(subdomain.get("records")
+                .and_then(|records| explain_records(records, &owner))
+                .unwrap_or_else(|| {
+                    format!("{}: {}", owner, explain_keys::<config::SubDomain>(subdomain, &[]).unwrap_or(err))
+                }));
+        }
+    }
+
+    None
+}
+
+/// explains why a zone, template or profile zone failed to load. the
+/// subdomains are checked first to point at the record that is wrong
+fn explain_zone<T: DeserializeOwned>(zone: &Value, owner: &str) -> Option<String> {
+    let err = check::<T>(zone).err()?;
+
+    Some(zone.get("subdomains")
+        .and_then(|subdomains| explain_subdomains(subdomains, owner))
+        .unwrap_or_else(|| format!("{}: {}", owner, explain_keys::<T>(zone, &[]).unwrap_or(err))))
+}
+
+/// looks for the part of a config that failed to load and returns a message
+/// naming the zone, subdomain and record that is wrong. returns None when
+/// the problem is not inside of a zone, template or profile
+pub fn explain(config: &Value) -> Option<String> {
+    for zone in config.get("zones").and_then(Value::as_array).into_iter().flatten() {
+        let owner = format!("zone \"{}\"", name_of(zone));
+
+        if let Some(msg) = explain_zone::<config::Zone>(zone, &owner) {
+            return Some(msg);
+        }
+    }
+
+    for (name, template) in config.get("templates").and_then(Value::as_object).into_iter().flatten() {
+        let owner = format!("template \"{}\"", name);
+
+        if let Some(msg) = explain_zone::<config::ZoneTemplate>(template, &owner) {
+            return Some(msg);
+        }
+    }
+
+    for (name, profile) in config.get("profiles").and_then(Value::as_object).into_iter().flatten() {
+        let err = match check::<config::Profile>(profile) {
+            Ok(()) => continue,
+            Err(err) => err
+        };
+
+        for (zone, overlay) in profile.get("zones").and_then(Value::as_object).into_iter().flatten() {
+            let owner = format!("zone \"{}\" of profile \"{}\"", zone, name);
+
+            if let Some(msg) = explain_zone::<config::ProfileZone>(overlay, &owner) {
+                return Some(msg);
+            }
+        }
+
+        return Some(format!(
+            "profile \"{}\": {}", name, explain_keys::<config::Profile>(profile, &[]).unwrap_or(err)
+        ));
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn explain_yaml(config: &str) -> Option<String> {
+        explain(&serde_yaml::from_str(config).unwrap())
+    }
+
+    #[test]
+    fn missing_fields_are_named_by_serde() {
+        // explain_keys relies on this to tell fields the probe left out
+        // apart from fields that are wrong
+        let err = check::<config::SubDomainRecord>(&serde_json::json!({"type": "A"})).unwrap_err();
+
+        assert!(err.starts_with("missing field"), "{}", err);
+    }
+
+    #[test]
+    fn bad_record_field() {
+        let msg = explain_yaml("
+name: test
+zones:
+  - name: example
+    subdomains:
+      - name: '@'
+        records:
+          - {type: A, address: [true]}
+").unwrap();
+
+        assert!(msg.starts_with("record 1 (A) of subdomain \"@\" of zone \"example\": field \"address\": "), "{}", msg);
+    }
+
+    #[test]
+    fn unknown_record_type() {
+        let msg = explain_yaml("
+name: test
+zones:
+  - name: example
+    subdomains:
+      - name: www
+        records:
+          - {type: A, address: 10.0.0.1}
+          - {type: Nope, value: x}
+").unwrap();
+
+        assert!(msg.starts_with("record 2 (Nope) of subdomain \"www\" of zone \"example\": field \"type\": unknown variant `Nope`"), "{}", msg);
+    }
+
+    #[test]
+    fn bad_profile_zone_field() {
+        let msg = explain_yaml("
+name: test
+zones: []
+profiles:
+  staging:
+    zones:
+      example: {ttl: soon}
+").unwrap();
+
+        assert!(msg.starts_with("zone \"example\" of profile \"staging\": field \"ttl\": invalid type"), "{}", msg);
+    }
+
+    #[test]
+    fn valid_config() {
+        assert_eq!(explain_yaml("
+name: test
+zones:
+  - name: example
+    subdomains:
+      - name: www
+        records: [{type: A, address: 10.0.0.1}]
+"), None);
+    }
+}
@@ -0,0 +1,206 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::config;
+use crate::context;
+use crate::deploy;
+use crate::dns;
+use crate::error;
+use crate::template;
+
+const DEFAULT_REFRESH: u32 = 86400;
+const DEFAULT_RETRY: u32 = 7200;
+const DEFAULT_EXPIRE: u32 = 3600000;
+const DEFAULT_NCT: u32 = 3600;
+
+/// merges two soa blocks. values of the overriding block win
+pub fn merge(base: Option<config::SoaDetail>, overrides: Option<config::SoaDetail>) -> Option<config::SoaDetail> {
+    match (base, overrides) {
+        (Some(base), Some(overrides)) => Some(config::SoaDetail {
+            primary: overrides.primary.or(base.primary),
+            email: overrides.email.or(base.email),
+            serial: overrides.serial.or(base.serial),
+            refresh: overrides.refresh.or(base.refresh),
+            retry: overrides.retry.or(base.retry),
+            expire: overrides.expire.or(base.expire),
+            nct: overrides.nct.or(base.nct)
+        }),
+        (base, overrides) => overrides.or(base)
+    }
+}
+
+/// converts the email of an soa record to the RNAME form. an address like
+/// "host.master@example.com" becomes "host\.master.example.com.". values
+/// without an "@" are treated as domains relative to the zone
+pub fn rname(zone: &context::ZoneContext, email: String) -> error::Result<String> {
+    let (local, domain) = match email.split_once('@') {
+        Some(split) => split,
+        None => return zone.with_domain(email)
+    };
+
+    if local.is_empty() || domain.is_empty() || email.contains(char::is_whitespace) {
+        return Err(error::RuntimeError::ConfigError(format!("invalid soa email given: \"{}\"", email)));
+    }
+
+    let mut rtn = String::with_capacity(email.len() + 4);
+
+    for c in local.chars() {
+        if c == '.' || c == '\\' {
+            rtn.push('\\');
+        }
+
+        rtn.push(c);
+    }
+
+    rtn.push('.');
+    rtn.push_str(&dns::domain_to_ascii(domain).map_err(|label| error::RuntimeError::ConfigError(
+        format!("invalid domain label \"{}\" in soa email \"{}\"", label, email)
+    ))?);
+
+    if !rtn.ends_with('.') {
+        rtn.push('.');
+    }
+
+    Ok(rtn)
+}
+
+/// serial used when none is given, today as YYYYMMDD00. zones that change
+/// again on the same day have their serial bumped past the existing one
+fn default_serial() -> u32 {
+    let secs = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+    let (year, month, day) = deploy::civil_date(secs);
+
+    (year * 1000000 + month * 10000 + day * 100) as u32
+}
+
+/// builds the SOA and NS records of a zone from the soa and nameservers
+/// blocks of the zone and config. nameservers of the zone are relative to
+/// the zone while those of the config have to be absolute
+pub fn zone_records(
+    config: &context::ConfigContext,
+    zone: &context::ZoneContext
+) -> error::Result<Vec<dns::record::Record>> {
+    let scope = template::Scope::new(vec!(zone.get_keys_ref(), config.get_keys_ref()));
+    let mut nameservers = Vec::with_capacity(zone.get_nameservers_ref().len());
+    let mut rtn = Vec::with_capacity(nameservers.capacity() + 1);
+
+    for nameserver in zone.get_nameservers_ref() {
+        let nameserver = scope.render(nameserver)?;
+
+        if zone.get_config_nameservers() && !nameserver.ends_with('.') {
+            return Err(error::RuntimeError::ConfigError(format!(
+                "nameserver \"{}\" of the config must be absolute since every zone uses it", nameserver
+            )));
+        }
+
+        nameservers.push(zone.with_domain(nameserver)?);
+    }
+
+    if let Some(soa) = zone.get_soa_ref() {
+        let primary = match &soa.primary {
+            Some(primary) => zone.with_domain(scope.render(primary)?)?,
+            None => nameservers.first().cloned().ok_or_else(|| error::RuntimeError::ConfigError(format!(
+                "soa of zone \"{}\" requires a primary or at least one nameserver", zone.get_name_ref()
+            )))?
+        };
+        let email = soa.email.as_ref().ok_or_else(|| error::RuntimeError::ConfigError(format!(
+            "soa of zone \"{}\" requires an email", zone.get_name_ref()
+        )))?;
+
+        rtn.push(dns::record::Record::Soa {
+            name: zone.get_domain(),
+            ttl: zone.get_ttl(),
+            domain: primary,
+            email: rname(zone, scope.render(email)?)?,
+            serial: soa.serial.unwrap_or_else(default_serial),
+            refresh: soa.refresh.unwrap_or(DEFAULT_REFRESH),
+            retry: soa.retry.unwrap_or(DEFAULT_RETRY),
+            expire: soa.expire.unwrap_or(DEFAULT_EXPIRE),
+            nct: soa.nct.unwrap_or(DEFAULT_NCT)
+        });
+    }
+
+    for nameserver in nameservers {
+        rtn.push(dns::record::Record::Ns {
+            name: zone.get_domain(),
+            ttl: zone.get_ttl(),
+            domain: nameserver
+        });
+    }
+
+    Ok(rtn)
+}
+
+/// checks that every zone has exactly one SOA record and moves it to the
+/// start of the zone
+pub fn check_zones(storage: &mut dns::ZoneStorage) -> error::Result<()> {
+    for zone in storage.get_zones_mut().values_mut() {
+        match zone.move_soa_first() {
+            1 => {},
+            0 => return Err(error::RuntimeError::ConfigError(format!(
+                "zone \"{}\" has no SOA record. add a soa block to the zone or config", zone.get_name_ref()
+            ))),
+            count => return Err(error::RuntimeError::ConfigError(format!(
+                "zone \"{}\" has {} SOA records. only one is allowed", zone.get_name_ref(), count
+            )))
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path};
+
+    use super::*;
+
+    fn contexts(config: &str, zone: &str) -> (context::ConfigContext, context::ZoneContext) {
+        let config = serde_yaml::from_str::<config::Config>(&format!("{}\nzones: []", config)).unwrap();
+        let zone = serde_yaml::from_str::<config::Zone>(zone).unwrap();
+        let config = context::ConfigContext::new(config, Path::new("/etc/zones/config.yaml"));
+        let zone = context::ZoneContext::new(&config, zone).unwrap();
+
+        (config, zone)
+    }
+
+    fn rname_of(email: &str) -> error::Result<String> {
+        let (_, zone) = contexts("name: test", "name: example.com");
+
+        rname(&zone, email.to_owned())
+    }
+
+    #[test]
+    fn emails() {
+        assert_eq!(rname_of("hostmaster@example.com").unwrap(), "hostmaster.example.com.");
+        assert_eq!(rname_of("host.master@example.net.").unwrap(), "host\\.master.example.net.");
+        assert_eq!(rname_of("back\\slash@example.com").unwrap(), "back\\\\slash.example.com.");
+        assert_eq!(rname_of("admin@bücher.example").unwrap(), "admin.xn--bcher-kva.example.");
+        assert_eq!(rname_of("hostmaster").unwrap(), "hostmaster.example.com.");
+        assert_eq!(rname_of("hostmaster.example.org.").unwrap(), "hostmaster.example.org.");
+    }
+
+    #[test]
+    fn invalid_emails() {
+        assert!(rname_of("@example.com").is_err());
+        assert!(rname_of("hostmaster@").is_err());
+        assert!(rname_of("host master@example.com").is_err());
+    }
+
+    #[test]
+    fn config_nameservers() {
+        let soa = "soa: {email: hostmaster@example.com, serial: 1}";
+        let (config, zone) = contexts(&format!("name: test\nnameservers: [ns1.example.net.]\n{}", soa), "name: example.com");
+        let records = zone_records(&config, &zone).unwrap();
+
+        assert_eq!(records.len(), 2);
+        assert!(matches!(&records[1], dns::record::Record::Ns {domain, ..} if domain == "ns1.example.net."));
+
+        let (config, zone) = contexts(&format!("name: test\nnameservers: [ns1]\n{}", soa), "name: example.com");
+        assert!(zone_records(&config, &zone).is_err());
+
+        let (config, zone) = contexts(&format!("name: test\n{}", soa), "{name: example.com, nameservers: [ns1]}");
+        let records = zone_records(&config, &zone).unwrap();
+
+        assert!(matches!(&records[0], dns::record::Record::Soa {domain, ..} if domain == "ns1.example.com."));
+    }
+}
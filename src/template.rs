@@ -0,0 +1,371 @@
+use crate::config;
+use crate::error;
+
+fn valid_name(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// adds the record type and field to a failed render. free text fields like
+/// txt values are templated as well, so the error points at the braces
+/// escape for values that contain literal braces
+fn in_field(kind: &str, field: &str, result: error::Result<()>) -> error::Result<()> {
+    result.map_err(|err| match err {
+        error::RuntimeError::ConfigError(msg) => error::RuntimeError::ConfigError(format!(
+            "{} record field \"{}\": {}. use \"{{{{\" and \"}}}}\" for literal braces", kind, field, msg
+        )),
+        err => err
+    })
+}
+
+/// renders templates using a list of keys maps. the first map containing a
+/// key wins.
+///
+/// supported syntax:
+///  - "{key}" value of a key, the value is rendered as well
+///  - "{key:-default}" value of a key or the default if it does not exist
+///  - "{env:VAR}" value of an environment variable, defaults are allowed
+///  - "{{" and "}}" literal braces
+pub struct Scope<'a> {
+    sources: Vec<&'a config::KeysMap>
+}
+
+impl<'a> Scope<'a> {
+
+    pub fn new(sources: Vec<&'a config::KeysMap>) -> Scope<'a> {
+        Scope { sources }
+    }
+
+    fn find_key(&self, key: &str) -> Option<&'a String> {
+        self.sources.iter().find_map(|keys| keys.get(key))
+    }
+
+    pub fn render(&self, given: &str) -> error::Result<String> {
+        self.render_with(given, &mut Vec::new())
+    }
+
+    fn render_with(&self, given: &str, visiting: &mut Vec<String>) -> error::Result<String> {
+        let mut rtn = String::with_capacity(given.len());
+        let mut chars = given.char_indices().peekable();
+
+        while let Some((index, c)) = chars.next() {
+            match c {
+                '{' => {
+                    if chars.next_if(|(_, c)| *c == '{').is_some() {
+                        rtn.push('{');
+                        continue;
+                    }
+
+                    let mut depth = 1;
+                    let mut end = None;
+
+                    for (i, c) in chars.by_ref() {
+                        if c == '{' {
+                            depth += 1;
+                        } else if c == '}' {
+                            depth -= 1;
+
+                            if depth == 0 {
+                                end = Some(i);
+                                break;
+                            }
+                        }
+                    }
+
+                    let end = end.ok_or_else(|| error::RuntimeError::ConfigError(format!(
+                        "unclosed key reference in \"{}\"", given
+                    )))?;
+
+                    rtn.push_str(&self.resolve(&given[index + 1..end], given, visiting)?);
+                },
+                '}' => {
+                    if chars.next_if(|(_, c)| *c == '}').is_none() {
+                        return Err(error::RuntimeError::ConfigError(format!(
+                            "unmatched \"}}\" in \"{}\". use \"}}}}\" for a literal brace", given
+                        )));
+                    }
+
+                    rtn.push('}');
+                },
+                _ => rtn.push(c)
+            }
+        }
+
+        Ok(rtn)
+    }
+
+    fn resolve(&self, reference: &str, given: &str, visiting: &mut Vec<String>) -> error::Result<String> {
+        let (name, default) = match reference.split_once(":-") {
+            Some((name, default)) => (name, Some(default)),
+            None => (reference, None)
+        };
+        let (env, name) = match name.strip_prefix("env:") {
+            Some(name) => (true, name),
+            None => (false, name)
+        };
+
+        if !valid_name(name) {
+            return Err(error::RuntimeError::ConfigError(format!(
+                "invalid key reference \"{{{}}}\" in \"{}\"", reference, given
+            )));
+        }
+
+        if env {
+            if let Ok(value) = std::env::var(name) {
+                return Ok(value);
+            }
+        } else if let Some(value) = self.find_key(name) {
+            if visiting.iter().any(|v| v == name) {
+                return Err(error::RuntimeError::ConfigError(format!(
+                    "key loop detected: {} -> {}", visiting.join(" -> "), name
+                )));
+            }
+
+            visiting.push(name.to_owned());
+            let rendered = self.render_with(value, visiting)?;
+            visiting.pop();
+
+            return Ok(rendered);
+        }
+
+        match default {
+            Some(default) => self.render_with(default, visiting),
+            None if env => Err(error::RuntimeError::ConfigError(format!(
+                "environment variable is not set: {}", name
+            ))),
+            None => Err(error::RuntimeError::ConfigError(format!(
+                "failed to find requested key: {}", name
+            )))
+        }
+    }
+
+    fn string(&self, value: &mut String) -> error::Result<()> {
+        *value = self.render(value)?;
+
+        Ok(())
+    }
+
+    fn option(&self, value: &mut Option<String>) -> error::Result<()> {
+        if let Some(value) = value {
+            self.string(value)?;
+        }
+
+        Ok(())
+    }
+
+    fn list(&self, value: &mut Option<Vec<String>>) -> error::Result<()> {
+        for value in value.iter_mut().flatten() {
+            self.string(value)?;
+        }
+
+        Ok(())
+    }
+
+    fn ipv4_value(&self, value: &mut config::Ipv4Value) -> error::Result<()> {
+        if let config::Ipv4Value::Str(value) = value {
+            self.string(value)?;
+        }
+
+        Ok(())
+    }
+
+    fn ipv4_type(&self, value: &mut config::Ipv4Type) -> error::Result<()> {
+        match value {
+            config::Ipv4Type::Ip(_) => Ok(()),
+            config::Ipv4Type::Str(value) => self.string(value),
+            config::Ipv4Type::Detail(detail) => self.ipv4_value(&mut detail.ip),
+            config::Ipv4Type::Pool(pool_ref) => self.string(&mut pool_ref.pool)
+        }
+    }
+
+    fn ipv6_value(&self, value: &mut config::Ipv6Value) -> error::Result<()> {
+        if let config::Ipv6Value::Str(value) = value {
+            self.string(value)?;
+        }
+
+        Ok(())
+    }
+
+    fn ipv6_type(&self, value: &mut config::Ipv6Type) -> error::Result<()> {
+        match value {
+            config::Ipv6Type::Ip(_) => Ok(()),
+            config::Ipv6Type::Str(value) => self.string(value),
+            config::Ipv6Type::Detail(detail) => self.ipv6_value(&mut detail.ip),
+            config::Ipv6Type::Pool(pool_ref) => self.string(&mut pool_ref.pool)
+        }
+    }
+
+    fn ptr_value(&self, value: &mut config::PtrValue) -> error::Result<()> {
+        if let config::PtrValue::Str(value) = value {
+            self.string(value)?;
+        }
+
+        Ok(())
+    }
+
+    fn svc_params(&self, params: &mut Option<config::SvcParams>) -> error::Result<()> {
+        if let Some(params) = params {
+            self.list(&mut params.mandatory)?;
+            self.list(&mut params.alpn)?;
+            self.option(&mut params.ech)?;
+
+            for hint in params.ipv4hint.iter_mut().flatten() {
+                self.ipv4_value(hint)?;
+            }
+
+            for hint in params.ipv6hint.iter_mut().flatten() {
+                self.ipv6_value(hint)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// renders every string field of a record. errors name the type of the
+    /// record and the field that failed to render
+    pub fn render_record(&self, mut record: config::Record) -> error::Result<config::Record> {
+        let kind = record.get_type();
+
+        match &mut record {
+            config::Record::Soa {domain, email, ..} => {
+                in_field(kind, "domain", self.string(domain))?;
+                in_field(kind, "email", self.string(email))?;
+            },
+            config::Record::Ns {domain} => in_field(kind, "domain", self.string(domain))?,
+            config::Record::A {address, ..} => match address {
+                config::Ipv4Address::Single(single) => in_field(kind, "address", self.ipv4_type(single))?,
+                config::Ipv4Address::Multiple(list) => for addr in list {
+                    in_field(kind, "address", self.ipv4_type(addr))?;
+                }
+            },
+            config::Record::Aaaa {address, ..} => match address {
+                config::Ipv6Address::Single(single) => in_field(kind, "address", self.ipv6_type(single))?,
+                config::Ipv6Address::Multiple(list) => for addr in list {
+                    in_field(kind, "address", self.ipv6_type(addr))?;
+                }
+            },
+            config::Record::Mx {domain, ..} => in_field(kind, "domain", self.string(domain))?,
+            config::Record::Cname {alias} => in_field(kind, "alias", self.string(alias))?,
+            config::Record::Txt {value} => match value {
+                config::TxtValue::Single(single) => in_field(kind, "value", self.string(single))?,
+                config::TxtValue::Multiple(list) => for value in list {
+                    in_field(kind, "value", self.string(value))?;
+                }
+            },
+            config::Record::Ptr {address} => match address {
+                config::PtrAddress::Single(single) => in_field(kind, "address", self.ptr_value(single))?,
+                config::PtrAddress::Multiple(list) => for addr in list {
+                    in_field(kind, "address", self.ptr_value(addr))?;
+                }
+            },
+            config::Record::Spf(detail) => {
+                in_field(kind, "include", self.list(&mut detail.include))?;
+                in_field(kind, "ip4", self.list(&mut detail.ip4))?;
+                in_field(kind, "ip6", self.list(&mut detail.ip6))?;
+                in_field(kind, "redirect", self.option(&mut detail.redirect))?;
+            },
+            config::Record::Dkim(detail) => {
+                in_field(kind, "selector", self.string(&mut detail.selector))?;
+                in_field(kind, "key", self.option(&mut detail.key))?;
+                in_field(kind, "key_file", self.option(&mut detail.key_file))?;
+            },
+            config::Record::Dmarc(detail) => {
+                in_field(kind, "rua", self.list(&mut detail.rua))?;
+                in_field(kind, "ruf", self.list(&mut detail.ruf))?;
+            },
+            config::Record::Tlsa(detail) => {
+                in_field(kind, "data", self.option(&mut detail.data))?;
+                in_field(kind, "cert_file", self.option(&mut detail.cert_file))?;
+            },
+            config::Record::Sshfp(detail) => {
+                in_field(kind, "fingerprint", self.option(&mut detail.fingerprint))?;
+                in_field(kind, "key_file", self.option(&mut detail.key_file))?;
+            },
+            config::Record::Svcb(detail) |
+            config::Record::Https(detail) => {
+                in_field(kind, "target", self.string(&mut detail.target))?;
+                in_field(kind, "params", self.svc_params(&mut detail.params))?;
+            }
+        }
+
+        Ok(record)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys(pairs: &[(&str, &str)]) -> config::KeysMap {
+        pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    #[test]
+    fn nested_keys() {
+        let zone = keys(&[("host", "www.{domain}"), ("domain", "zone.example")]);
+        let config = keys(&[("domain", "config.example"), ("mail", "mx.{domain}")]);
+        let scope = Scope::new(vec!(&zone, &config));
+
+        assert_eq!(scope.render("{host}.").unwrap(), "www.zone.example.");
+        assert_eq!(scope.render("{mail}").unwrap(), "mx.zone.example");
+        assert!(scope.render("{missing}").is_err());
+    }
+
+    #[test]
+    fn key_loop() {
+        let map = keys(&[("a", "{b}"), ("b", "x.{a}"), ("c", "{c}")]);
+        let scope = Scope::new(vec!(&map));
+
+        assert!(scope.render("{a}").unwrap_err().get_msg().contains("key loop detected: a -> b -> a"));
+        assert!(scope.render("{c}").is_err());
+    }
+
+    #[test]
+    fn defaults() {
+        let map = keys(&[("ttl", "300"), ("fallback", "60")]);
+        let scope = Scope::new(vec!(&map));
+
+        assert_eq!(scope.render("{ttl:-3600}").unwrap(), "300");
+        assert_eq!(scope.render("{other:-3600}").unwrap(), "3600");
+        assert_eq!(scope.render("{other:-{fallback}}").unwrap(), "60");
+        assert_eq!(scope.render("{other:-}").unwrap(), "");
+    }
+
+    #[test]
+    fn env() {
+        std::env::set_var("DNS_ZONES_BUILDER_TEMPLATE_TEST", "from-env");
+        let map = keys(&[]);
+        let scope = Scope::new(vec!(&map));
+
+        assert_eq!(scope.render("{env:DNS_ZONES_BUILDER_TEMPLATE_TEST}").unwrap(), "from-env");
+        assert_eq!(scope.render("{env:DNS_ZONES_BUILDER_TEMPLATE_UNSET:-none}").unwrap(), "none");
+        assert!(scope.render("{env:DNS_ZONES_BUILDER_TEMPLATE_UNSET}").is_err());
+    }
+
+    #[test]
+    fn escaping() {
+        let map = keys(&[("key", "value")]);
+        let scope = Scope::new(vec!(&map));
+
+        assert_eq!(scope.render("{{key}} {key}").unwrap(), "{key} value");
+        assert_eq!(scope.render("}}{{").unwrap(), "}{");
+        assert!(scope.render("{key").is_err());
+        assert!(scope.render("key}").is_err());
+        assert!(scope.render("{bad name}").is_err());
+    }
+
+    #[test]
+    fn record_braces() {
+        let map = keys(&[]);
+        let scope = Scope::new(vec!(&map));
+        let txt = |value: &str| config::Record::Txt { value: config::TxtValue::Single(value.to_owned()) };
+
+        match scope.render_record(txt("json={{\"a\":1}}")).unwrap() {
+            config::Record::Txt { value } => assert_eq!(value, config::TxtValue::Single("json={\"a\":1}".to_owned())),
+            record => panic!("unexpected record {:?}", record)
+        }
+
+        let msg = scope.render_record(txt("json={\"a\":1}")).unwrap_err().get_msg();
+        assert!(msg.contains("TXT record field \"value\": invalid key reference"), "{}", msg);
+        assert!(msg.contains("use \"{{\" and \"}}\" for literal braces"), "{}", msg);
+    }
+}
@@ -0,0 +1,212 @@
+use std::fmt::{Write};
+use std::path::{Path, PathBuf};
+
+use crate::config;
+use crate::deploy;
+use crate::error;
+
+/// checks if an entry with the given views is part of the view. entries
+/// without views are part of every view
+fn in_view(tags: &Option<Vec<String>>, view: &str, views: &[config::View], owner: &str) -> error::Result<bool> {
+    let tags = match tags {
+        Some(tags) => tags,
+        None => return Ok(true)
+    };
+
+    for tag in tags {
+        if !views.iter().any(|v| v.name == *tag) {
+            return Err(error::RuntimeError::ConfigError(format!("unknown view \"{}\" given for {}", tag, owner)));
+        }
+    }
+
+    Ok(tags.iter().any(|tag| tag == view))
+}
+
+/// checks that the views of a record are part of the views of its
+/// subdomain. a record tagged for a view its subdomain is excluded from
+/// would be dropped from every view
+fn check_record_views(subdomain: &config::SubDomain, record: &config::SubDomainRecord, owner: &str) -> error::Result<()> {
+    let (allowed, tags) = match (&subdomain.views, &record.views) {
+        (Some(allowed), Some(tags)) => (allowed, tags),
+        _ => return Ok(())
+    };
+
+    match tags.iter().find(|tag| !allowed.contains(tag)) {
+        Some(tag) => Err(error::RuntimeError::ConfigError(format!(
+            "{} record of {} is tagged for view \"{}\" but the subdomain is only part of views {}",
+            record.record.get_type(), owner, tag, allowed.join(", ")
+        ))),
+        None => Ok(())
+    }
+}
+
+/// removes every subdomain and record that is not part of the given view
+pub fn filter_zones(
+    zones: Vec<config::Zone>,
+    view: &str,
+    views: &[config::View]
+) -> error::Result<Vec<config::Zone>> {
+    let mut rtn = Vec::with_capacity(zones.len());
+
+    for mut zone in zones {
+        let mut subdomains = Vec::with_capacity(zone.subdomains.len());
+
+        for mut subdomain in std::mem::take(&mut zone.subdomains) {
+            let owner = format!("subdomain \"{}\" of zone \"{}\"", subdomain.name, zone.name);
+
+            for record in &subdomain.records {
+                check_record_views(&subdomain, record, &owner)?;
+            }
+
+            if !in_view(&subdomain.views, view, views, &owner)? {
+                continue;
+            }
+
+            let mut records = Vec::with_capacity(subdomain.records.len());
+
+            for record in std::mem::take(&mut subdomain.records) {
+                if in_view(&record.views, view, views, &owner)? {
+                    records.push(record);
+                }
+            }
+
+            subdomain.records = records;
+            subdomains.push(subdomain);
+        }
+
+        zone.subdomains = subdomains;
+        rtn.push(zone);
+    }
+
+    Ok(rtn)
+}
+
+/// returns the path of a zone file for the given view
+pub fn zone_path(directory: &str, zone: &str, view: Option<&str>) -> PathBuf {
+    let mut path = PathBuf::from(directory);
+
+    match view {
+        Some(view) => path.push(format!("{}.{}", zone, view)),
+        None => path.push(zone)
+    }
+
+    path
+}
+
+/// the view blocks for named.conf. every view lists the origin and file of
+/// the zones that were built for it. named resolves relative paths against
+/// its own directory, so the files are given as absolute paths
+fn named_conf(views: &[config::View], zones: &[Vec<(String, PathBuf)>]) -> error::Result<String> {
+    let mut contents = String::new();
+
+    writeln!(contents, "// view blocks generated from dns-zones-builder")?;
+
+    for (view, view_zones) in views.iter().zip(zones) {
+        writeln!(contents, "\nview \"{}\" {{", view.name)?;
+
+        match &view.match_clients {
+            Some(clients) => writeln!(contents, "    match-clients {{ {}; }};", clients.join("; "))?,
+            None => writeln!(contents, "    match-clients {{ any; }};")?
+        }
+
+        for (origin, zone_path) in view_zones {
+            writeln!(contents, "\n    zone \"{}\" {{", origin)?;
+            writeln!(contents, "        type master;")?;
+            writeln!(contents, "        file \"{}\";", std::path::absolute(zone_path)?.display())?;
+            writeln!(contents, "    }};")?;
+        }
+
+        writeln!(contents, "}};")?;
+    }
+
+    Ok(contents)
+}
+
+/// writes the view blocks for named.conf
+pub fn write_named_conf(
+    path: &Path,
+    views: &[config::View],
+    zones: &[Vec<(String, PathBuf)>]
+) -> error::Result<()> {
+    deploy::write(path, named_conf(views, zones)?.as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn views() -> Vec<config::View> {
+        serde_yaml::from_str("[{name: internal, match_clients: [10.0.0.0/8, localhost]}, {name: external}]").unwrap()
+    }
+
+    fn filter(zones: &str, view: &str) -> error::Result<Vec<config::Zone>> {
+        filter_zones(serde_yaml::from_str(zones).unwrap(), view, &views())
+    }
+
+    const ZONES: &str = "
+- name: example
+  subdomains:
+    - name: www
+      records:
+        - {type: A, address: 10.0.0.1, views: [internal]}
+        - {type: A, address: 192.0.2.1, views: [external]}
+        - {type: Txt, value: both}
+    - name: intranet
+      views: [internal]
+      records:
+        - {type: A, address: 10.0.0.2}
+";
+
+    #[test]
+    fn filtered_views() {
+        let internal = filter(ZONES, "internal").unwrap();
+        let subdomains = &internal[0].subdomains;
+        assert_eq!(subdomains.iter().map(|s| s.name.as_str()).collect::<Vec<_>>(), vec!("www", "intranet"));
+        assert_eq!(subdomains[0].records.len(), 2);
+        assert!(matches!(
+            &subdomains[0].records[0].record,
+            config::Record::A {address: config::Ipv4Address::Single(config::Ipv4Type::Ip(ip)), ..} if ip.octets() == [10, 0, 0, 1]
+        ));
+
+        let external = filter(ZONES, "external").unwrap();
+        let subdomains = &external[0].subdomains;
+        assert_eq!(subdomains.iter().map(|s| s.name.as_str()).collect::<Vec<_>>(), vec!("www"));
+        assert_eq!(subdomains[0].records.len(), 2);
+    }
+
+    #[test]
+    fn invalid_views() {
+        let unknown = filter("[{name: example, subdomains: [{name: www, views: [lab], records: []}]}]", "internal");
+        assert!(unknown.err().unwrap().get_msg().contains("unknown view \"lab\" given for subdomain \"www\" of zone \"example\""));
+
+        let excluded = filter("
+- name: example
+  subdomains:
+    - name: www
+      views: [internal]
+      records:
+        - {type: A, address: 192.0.2.1, views: [external]}
+", "internal");
+        assert!(excluded.err().unwrap().get_msg().contains("is tagged for view \"external\" but the subdomain is only part of views internal"));
+    }
+
+    #[test]
+    fn view_blocks() {
+        let zones = vec!(
+            vec!(("example.".to_owned(), PathBuf::from("/zones/example.internal"))),
+            vec!(("example.".to_owned(), PathBuf::from("zones/example.external")))
+        );
+        let contents = named_conf(&views(), &zones).unwrap();
+        let relative = std::env::current_dir().unwrap().join("zones/example.external");
+
+        assert!(contents.contains("view \"internal\" {\n    match-clients { 10.0.0.0/8; localhost; };\n\n    zone \"example.\" {\n        type master;\n        file \"/zones/example.internal\";\n    };\n};\n"));
+        assert!(contents.contains("view \"external\" {\n    match-clients { any; };\n"));
+        assert!(contents.contains(&format!("        file \"{}\";\n", relative.display())));
+    }
+
+    #[test]
+    fn zone_paths() {
+        assert_eq!(zone_path("/zones", "example", Some("internal")), PathBuf::from("/zones/example.internal"));
+        assert_eq!(zone_path(".", "example", None), PathBuf::from("./example"));
+    }
+}
@@ -0,0 +1,301 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::time::{Duration};
+
+use inotify::{EventMask, Inotify, WatchDescriptor, WatchMask};
+use log::{error, info};
+
+use crate::error;
+
+/// time without new events to wait for before rebuilding
+const DEBOUNCE: Duration = Duration::from_millis(250);
+
+/// a config file and the files read by its last build
+struct Watched {
+    file: PathBuf,
+    read: HashSet<PathBuf>
+}
+
+/// the changes found after a burst of events
+enum Changes {
+    Files(HashSet<PathBuf>),
+
+    /// events were dropped by the kernel so anything may have changed
+    Overflow
+}
+
+impl Watched {
+
+    /// whether the changes touch any file read by the last build
+    fn affected_by(&self, changes: &Changes) -> bool {
+        match changes {
+            Changes::Files(changed) => !self.read.is_disjoint(changed),
+            Changes::Overflow => true
+        }
+    }
+}
+
+fn canonical(path: PathBuf) -> PathBuf {
+    std::fs::canonicalize(&path).unwrap_or(path)
+}
+
+/// builds a config and replaces the files it depends on. when the build
+/// fails the output of the last good build is left in place and the files
+/// read by it stay watched as well
+fn rebuild<F>(watched: &mut Watched, build: &mut F)
+where
+    F: FnMut(&Path, &mut Vec<PathBuf>) -> error::Result<()>
+{
+    let mut read = Vec::new();
+
+    info!("building {}", watched.file.display());
+
+    match build(&watched.file, &mut read) {
+        Ok(()) => {
+            watched.read = read.into_iter().map(canonical).collect();
+
+            info!("finished {}", watched.file.display());
+        },
+        Err(err) => {
+            watched.read.extend(read.into_iter().map(canonical));
+
+            error!("{}", err);
+            error!("build of {} failed. keeping the last good output", watched.file.display());
+        }
+    }
+}
+
+/// watches the directories of every read file. directories are watched
+/// instead of the files so that editors replacing a file are noticed
+fn add_watches(
+    inotify: &mut Inotify,
+    directories: &mut HashMap<WatchDescriptor, PathBuf>,
+    watched: &[Watched]
+) -> error::Result<()> {
+    let wanted: HashSet<&Path> = watched.iter()
+        .flat_map(|w| w.read.iter())
+        .filter_map(|path| path.parent())
+        .collect();
+
+    for directory in wanted {
+        if directories.values().any(|d| d == directory) || !directory.is_dir() {
+            continue;
+        }
+
+        let wd = inotify.watches().add(
+            directory,
+            WatchMask::CLOSE_WRITE | WatchMask::MOVED_TO | WatchMask::CREATE | WatchMask::DELETE
+        )?;
+
+        directories.insert(wd, directory.to_path_buf());
+    }
+
+    Ok(())
+}
+
+/// collects the changed paths from a buffer of events. returns false if
+/// the buffer was empty
+fn collect_events(
+    events: inotify::Events,
+    directories: &HashMap<WatchDescriptor, PathBuf>,
+    changed: &mut HashSet<PathBuf>,
+    overflow: &mut bool
+) -> bool {
+    let mut found = false;
+
+    for event in events {
+        found = true;
+
+        if event.mask.contains(EventMask::Q_OVERFLOW) {
+            *overflow = true;
+        } else if let (Some(directory), Some(name)) = (directories.get(&event.wd), event.name) {
+            changed.insert(directory.join(name));
+        }
+    }
+
+    found
+}
+
+/// blocks until files change and waits for a burst of changes to settle
+fn wait_for_changes(
+    inotify: &mut Inotify,
+    directories: &HashMap<WatchDescriptor, PathBuf>,
+    buffer: &mut [u8]
+) -> error::Result<Changes> {
+    let mut changed = HashSet::new();
+    let mut overflow = false;
+
+    collect_events(inotify.read_events_blocking(buffer)?, directories, &mut changed, &mut overflow);
+
+    loop {
+        std::thread::sleep(DEBOUNCE);
+
+        match inotify.read_events(buffer) {
+            Ok(events) => {
+                if !collect_events(events, directories, &mut changed, &mut overflow) {
+                    break;
+                }
+            },
+            Err(err) if err.kind() == std::io::ErrorKind::WouldBlock => break,
+            Err(err) => return Err(err.into())
+        }
+    }
+
+    if overflow {
+        Ok(Changes::Overflow)
+    } else {
+        Ok(Changes::Files(changed))
+    }
+}
+
+/// builds the given config files and rebuilds them whenever one of the
+/// files they read changes. a change rebuilds every zone of each config
+/// file that read the changed file, configs that did not read it are left
+/// alone. runs until interrupted
+pub fn run<F>(files: Vec<PathBuf>, mut build: F) -> error::Result<i32>
+where
+    F: FnMut(&Path, &mut Vec<PathBuf>) -> error::Result<()>
+{
+    let mut inotify = Inotify::init()?;
+    let mut directories: HashMap<WatchDescriptor, PathBuf> = HashMap::new();
+    let mut buffer = [0u8; 4096];
+    let mut watched: Vec<Watched> = files.into_iter()
+        .map(|file| Watched {
+            read: HashSet::from([file.clone()]),
+            file
+        })
+        .collect();
+
+    let mut rebuilt = true;
+
+    for entry in watched.iter_mut() {
+        rebuild(entry, &mut build);
+    }
+
+    loop {
+        if rebuilt {
+            add_watches(&mut inotify, &mut directories, &watched)?;
+
+            info!("watching for changes");
+        }
+
+        let changes = wait_for_changes(&mut inotify, &directories, &mut buffer)?;
+        rebuilt = false;
+
+        for entry in watched.iter_mut() {
+            if entry.affected_by(&changes) {
+                rebuild(entry, &mut build);
+                rebuilt = true;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn directory(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("dns-zones-builder-watch-{}-{}", std::process::id(), name));
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path).unwrap();
+
+        canonical(path)
+    }
+
+    fn watch(directory: &Path) -> (Inotify, HashMap<WatchDescriptor, PathBuf>) {
+        let inotify = Inotify::init().unwrap();
+        let wd = inotify.watches().add(directory, WatchMask::CLOSE_WRITE | WatchMask::DELETE).unwrap();
+
+        (inotify, HashMap::from([(wd, directory.to_path_buf())]))
+    }
+
+    fn paths(paths: &[&Path]) -> HashSet<PathBuf> {
+        paths.iter().map(|path| path.to_path_buf()).collect()
+    }
+
+    #[test]
+    fn events() {
+        let directory = directory("events");
+        let (mut inotify, directories) = watch(&directory);
+        let mut buffer = [0u8; 4096];
+        let mut changed = HashSet::new();
+        let mut overflow = false;
+
+        std::fs::write(directory.join("zone.yaml"), "name: example").unwrap();
+
+        let events = inotify.read_events_blocking(&mut buffer).unwrap();
+        assert!(collect_events(events, &directories, &mut changed, &mut overflow));
+        assert_eq!(changed, paths(&[&directory.join("zone.yaml")]));
+        assert!(!overflow);
+
+        // events of directories that are no longer known are skipped
+        std::fs::remove_file(directory.join("zone.yaml")).unwrap();
+        changed.clear();
+
+        let events = inotify.read_events_blocking(&mut buffer).unwrap();
+        assert!(collect_events(events, &HashMap::new(), &mut changed, &mut overflow));
+        assert!(changed.is_empty());
+    }
+
+    #[test]
+    fn burst_of_changes() {
+        let directory = directory("burst");
+        let (mut inotify, directories) = watch(&directory);
+        let mut buffer = [0u8; 4096];
+
+        std::fs::write(directory.join("config.yaml"), "name: test").unwrap();
+
+        let writer = {
+            let directory = directory.clone();
+
+            std::thread::spawn(move || {
+                std::thread::sleep(DEBOUNCE / 5);
+                std::fs::write(directory.join("zone.yaml"), "name: example").unwrap();
+            })
+        };
+
+        let changes = wait_for_changes(&mut inotify, &directories, &mut buffer).unwrap();
+        writer.join().unwrap();
+
+        match changes {
+            Changes::Files(changed) => assert_eq!(
+                changed, paths(&[&directory.join("config.yaml"), &directory.join("zone.yaml")])
+            ),
+            Changes::Overflow => panic!("unexpected overflow")
+        }
+    }
+
+    #[test]
+    fn affected_configs() {
+        let entry = Watched {
+            file: PathBuf::from("/etc/zones/config.yaml"),
+            read: paths(&[Path::new("/etc/zones/config.yaml"), Path::new("/etc/zones/example.yaml")])
+        };
+
+        assert!(entry.affected_by(&Changes::Files(paths(&[Path::new("/etc/zones/example.yaml")]))));
+        assert!(!entry.affected_by(&Changes::Files(paths(&[Path::new("/etc/zones/other.yaml")]))));
+        assert!(!entry.affected_by(&Changes::Files(HashSet::new())));
+        assert!(entry.affected_by(&Changes::Overflow));
+    }
+
+    #[test]
+    fn failed_rebuild_keeps_read_files() {
+        let directory = directory("rebuild");
+        let config = directory.join("config.yaml");
+        let zone = directory.join("zone.yaml");
+        let mut entry = Watched { file: config.clone(), read: paths(&[&config]) };
+
+        rebuild(&mut entry, &mut |_: &Path, read: &mut Vec<PathBuf>| {
+            read.push(zone.clone());
+            Ok(())
+        });
+        assert_eq!(entry.read, paths(&[&zone]));
+
+        rebuild(&mut entry, &mut |_: &Path, read: &mut Vec<PathBuf>| {
+            read.push(config.clone());
+            Err(error::RuntimeError::ConfigError("broken".to_owned()))
+        });
+        assert_eq!(entry.read, paths(&[&config, &zone]));
+    }
+}